@@ -0,0 +1,308 @@
+/**************************************************************************
+  Copyright 2024 Francesco Versaci (https://github.com/fversaci/)
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+**************************************************************************/
+
+//! Correction of whole files, split in paragraphs and sent to a pool of threads
+
+use anyhow::{anyhow, Result};
+use async_openai::types::{
+    CreateMessageRequestArgs, CreateRunRequest, CreateRunRequestArgs, MessageRole,
+};
+use async_openai::{config::OpenAIConfig, Client};
+use cesco_gpt::talks::{get_response, Talk};
+use clap::{Args, ValueEnum};
+use similar::{ChangeTag, TextDiff};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+#[derive(Args, Debug)]
+#[command(next_help_heading = "Batch correction")]
+pub struct BatchArgs {
+    /// Correct all the files in a directory, or matching a glob pattern
+    #[arg(long, global = true)]
+    pub batch: Option<String>,
+    /// Number of parallel correctors
+    #[arg(long, global = true, default_value_t = 1, requires = "batch")]
+    num: usize,
+    /// Style of the diff report
+    #[arg(long, global = true, value_enum, default_value_t = DiffStyle::Unified, requires = "batch")]
+    diff: DiffStyle,
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+enum DiffStyle {
+    /// Unified line diff, as produced by `diff -u`
+    Unified,
+    /// Word diff, marking [-removed-] and {+added+} words
+    Words,
+}
+
+struct Corrector {
+    client: Client<OpenAIConfig>,
+    thread_id: String,
+    run_request: CreateRunRequest,
+    presuff: (String, String),
+}
+
+impl Corrector {
+    async fn new(client: Client<OpenAIConfig>, talk: &Talk) -> Result<Self> {
+        let ts = talk.get_conv(&client).await?;
+        let run_request = CreateRunRequestArgs::default()
+            .assistant_id(&ts.asst.id)
+            .parallel_tool_calls(false)
+            .build()?;
+        Ok(Self {
+            client,
+            thread_id: ts.thread.id,
+            run_request,
+            presuff: ts.presuff,
+        })
+    }
+    async fn correct_str(&self, par: &str) -> Result<String> {
+        let (pre, suff) = &self.presuff;
+        let message = CreateMessageRequestArgs::default()
+            .role(MessageRole::User)
+            .content(format!("{pre}{par}\n{suff}"))
+            .build()?;
+        let _message_obj = self
+            .client
+            .threads()
+            .messages(&self.thread_id)
+            .create(message)
+            .await?;
+        let run = self
+            .client
+            .threads()
+            .runs(&self.thread_id)
+            .create(self.run_request.clone())
+            .await?;
+        let resp = get_response(&self.client, &run.id, &self.thread_id).await?;
+        Ok(resp.trim().to_string())
+    }
+}
+
+struct CorrectorPool {
+    correctors: Vec<Arc<Mutex<Corrector>>>,
+    curr: usize,
+    num: usize,
+}
+
+impl CorrectorPool {
+    async fn new(num: usize, client: Client<OpenAIConfig>, talk: &Talk) -> Result<Self> {
+        if num == 0 {
+            return Err(anyhow!("Error: pool must have at least 1 corrector."));
+        }
+        let mut correctors = Vec::new();
+        for _ in 0..num {
+            let corrector = Corrector::new(client.clone(), talk).await?;
+            correctors.push(Arc::new(Mutex::new(corrector)));
+        }
+        let ret = Self {
+            correctors,
+            curr: 0,
+            num,
+        };
+        Ok(ret)
+    }
+    fn get_corrector(&mut self) -> Arc<Mutex<Corrector>> {
+        let corrector = self.correctors[self.curr].clone();
+        self.curr = (self.curr + 1) % self.num; // Move to the next corrector
+        corrector
+    }
+    async fn cleanup(&self) -> Result<()> {
+        for corrector in &self.correctors {
+            let c = corrector.lock().await;
+            c.client.threads().delete(&c.thread_id).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Files to be corrected: the contents of a directory, or the matches of a glob
+fn list_files(pattern: &str) -> Result<Vec<PathBuf>> {
+    let path = Path::new(pattern);
+    let mut files: Vec<PathBuf> = if path.is_dir() {
+        fs::read_dir(path)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<Result<_, _>>()?
+    } else {
+        glob::glob(pattern)?.collect::<Result<_, _>>()?
+    };
+    // skip our own outputs, in case of repeated runs
+    files.retain(|f| {
+        f.is_file()
+            && !f
+                .extension()
+                .is_some_and(|ext| ext == "corrected" || ext == "diff")
+    });
+    files.sort();
+    if files.is_empty() {
+        return Err(anyhow!("No files found in {pattern}."));
+    }
+    Ok(files)
+}
+
+/// Paragraphs, separated by one or more blank lines, together with the
+/// separators around them (one more than the paragraphs), kept as they are
+fn split_paragraphs(text: &str) -> (Vec<String>, Vec<String>) {
+    let mut pars = Vec::new();
+    let mut seps = vec![String::new()];
+    let mut curr = String::new();
+    for line in text.split_inclusive('\n') {
+        if !line.trim().is_empty() {
+            curr.push_str(line);
+            continue;
+        }
+        if !curr.is_empty() {
+            close_paragraph(&mut curr, &mut pars, &mut seps);
+        }
+        seps.last_mut().unwrap().push_str(line);
+    }
+    if !curr.is_empty() {
+        close_paragraph(&mut curr, &mut pars, &mut seps);
+    }
+    (pars, seps)
+}
+
+/// Add the paragraph, moving its final line break to the next separator
+fn close_paragraph(curr: &mut String, pars: &mut Vec<String>, seps: &mut Vec<String>) {
+    let end = curr.trim_end_matches(['\r', '\n']).len();
+    seps.push(curr[end..].to_string());
+    curr.truncate(end);
+    pars.push(std::mem::take(curr));
+}
+
+/// Drop the blank lines of a corrected paragraph, not to add new boundaries
+fn merge_blank_lines(par: &str) -> String {
+    let lines: Vec<&str> = par.lines().filter(|l| !l.trim().is_empty()).collect();
+    lines.join("\n")
+}
+
+/// Text made of the paragraphs and of the separators around them
+fn join_paragraphs(pars: &[String], seps: &[String]) -> String {
+    let mut text = seps[0].clone();
+    for (par, sep) in pars.iter().zip(&seps[1..]) {
+        text.push_str(par);
+        text.push_str(sep);
+    }
+    text
+}
+
+fn with_ext(fname: &Path, ext: &str) -> PathBuf {
+    let mut out = fname.as_os_str().to_owned();
+    out.push(".");
+    out.push(ext);
+    PathBuf::from(out)
+}
+
+fn diff_report(
+    fname: &Path,
+    (orig_text, orig): (&str, &[String]),
+    (corr_text, corr): (&str, &[String]),
+    style: &DiffStyle,
+) -> String {
+    match style {
+        DiffStyle::Unified => {
+            let a = fname.display().to_string();
+            let b = with_ext(fname, "corrected").display().to_string();
+            TextDiff::from_lines(orig_text, corr_text)
+                .unified_diff()
+                .header(&a, &b)
+                .to_string()
+        }
+        DiffStyle::Words => {
+            let mut report = String::new();
+            for (i, (o, c)) in orig.iter().zip(corr).enumerate() {
+                if o == c {
+                    continue;
+                }
+                report.push_str(&format!("## Paragraph {}\n", i + 1));
+                for change in TextDiff::from_words(o, c).iter_all_changes() {
+                    let value = change.value();
+                    match change.tag() {
+                        ChangeTag::Equal => report.push_str(value),
+                        ChangeTag::Delete => report.push_str(&format!("[-{value}-]")),
+                        ChangeTag::Insert => report.push_str(&format!("{{+{value}+}}")),
+                    }
+                }
+                report.push_str("\n\n");
+            }
+            report
+        }
+    }
+}
+
+pub async fn run(client: &Client<OpenAIConfig>, talk: Talk, args: BatchArgs) -> Result<()> {
+    if !matches!(talk, Talk::Correct { .. }) {
+        return Err(anyhow!("Batch mode is only available for text correction."));
+    }
+    let pattern = args.batch.ok_or(anyhow!("Missing batch pattern."))?;
+    let files = list_files(&pattern)?;
+    let mut pool = CorrectorPool::new(args.num, client.clone(), &talk).await?;
+    for fname in files {
+        println!("Correcting {}", fname.display());
+        let text = fs::read_to_string(&fname)?;
+        let (pars, seps) = split_paragraphs(&text);
+        let jobs: Vec<_> = pars
+            .iter()
+            .map(|par| {
+                // Correct each paragraph concurrently using the pool
+                let par = par.clone();
+                let c = pool.get_corrector();
+                tokio::spawn(async move {
+                    let c = c.lock().await;
+                    c.correct_str(&par).await
+                })
+            })
+            .collect();
+        let mut corr = Vec::new();
+        for (job, par) in jobs.into_iter().zip(&pars) {
+            match job.await? {
+                Ok(c) => corr.push(merge_blank_lines(&c)),
+                Err(e) => {
+                    // keep the original paragraph, so that boundaries are preserved
+                    println!("Error detected: {e}. Copying verbatim paragraph.");
+                    corr.push(par.clone());
+                }
+            }
+        }
+        let out = join_paragraphs(&corr, &seps);
+        fs::write(with_ext(&fname, "corrected"), &out)?;
+        let report = diff_report(&fname, (&text, &pars), (&out, &corr), &args.diff);
+        fs::write(with_ext(&fname, "diff"), report)?;
+    }
+    pool.cleanup().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paragraphs_keep_their_separators() {
+        let text = "\nOne\ntwo\n\n  \n\nThree\r\n\t\nFour";
+        let (pars, seps) = split_paragraphs(text);
+        assert_eq!(pars, vec!["One\ntwo", "Three", "Four"]);
+        assert_eq!(seps, vec!["\n", "\n\n  \n\n", "\r\n\t\n", ""]);
+        assert_eq!(join_paragraphs(&pars, &seps), text);
+    }
+
+    #[test]
+    fn corrected_paragraphs_have_no_blank_lines() {
+        assert_eq!(merge_blank_lines("One\n\n \nTwo\nthree"), "One\nTwo\nthree");
+    }
+}
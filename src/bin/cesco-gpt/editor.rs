@@ -0,0 +1,106 @@
+/**************************************************************************
+  Copyright 2024 Francesco Versaci (https://github.com/fversaci/)
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+**************************************************************************/
+
+use anyhow::{anyhow, Result};
+use cesco_gpt::talks::Talk;
+use rustyline::{Cmd, DefaultEditor, KeyCode, KeyEvent, Modifiers};
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Command that opens the current draft in `$EDITOR`
+const EDIT_CMD: &str = "/edit";
+
+/// Line editor for the CLI, keeping a persistent history for each talk
+pub struct MsgReader {
+    rl: DefaultEditor,
+    hist_fn: Option<PathBuf>,
+}
+
+impl MsgReader {
+    pub fn new(talk: &Talk) -> Result<Self> {
+        let mut rl = DefaultEditor::new()?;
+        // Enter sends the message, Alt-Enter starts a new line
+        rl.bind_sequence(KeyEvent(KeyCode::Enter, Modifiers::ALT), Cmd::Newline);
+        let hist_fn = history_path(talk);
+        if let Some(hist_fn) = &hist_fn {
+            // a missing history file is fine, it will be created later
+            let _ = rl.load_history(hist_fn);
+        }
+        Ok(Self { rl, hist_fn })
+    }
+    /// Read a (possibly multi-line) message, wrapped in the talk delimiters
+    pub fn read_msg(&mut self, presuff: &(String, String)) -> Option<String> {
+        let (pre, suff) = presuff; // initial and final delimiters
+        let mut draft = String::new();
+        loop {
+            let line = self.rl.readline_with_initial("", (&draft, "")).ok()?;
+            match line.trim_end().strip_suffix(EDIT_CMD) {
+                Some(text) => match edit_draft(text.trim_end()) {
+                    Ok(text) => draft = text,
+                    Err(e) => {
+                        eprintln!("Error: {}", e);
+                        draft = text.trim_end().to_string();
+                    }
+                },
+                None => {
+                    draft = line;
+                    break;
+                }
+            }
+        }
+        if draft.trim().is_empty() {
+            return None;
+        }
+        self.add_history(&draft);
+        Some(format!("{pre}{draft}\n{suff}"))
+    }
+    fn add_history(&mut self, entry: &str) {
+        let _ = self.rl.add_history_entry(entry);
+        if let Some(hist_fn) = &self.hist_fn {
+            if let Err(e) = self.rl.append_history(hist_fn) {
+                eprintln!("Cannot save history to {}: {}", hist_fn.display(), e);
+            }
+        }
+    }
+}
+
+/// History file of the talk, e.g., `~/.local/share/cesco-gpt/history/correct-text.txt`
+fn history_path(talk: &Talk) -> Option<PathBuf> {
+    let dir = dirs::data_dir()?.join("cesco-gpt").join("history");
+    fs::create_dir_all(&dir).ok()?;
+    let name = talk.to_string().to_lowercase().replace(' ', "-");
+    Some(dir.join(format!("{name}.txt")))
+}
+
+/// Open the draft in `$VISUAL` or `$EDITOR` (default: `vi`) and return the edited text
+fn edit_draft(draft: &str) -> Result<String> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let tmp_fn = std::env::temp_dir().join(format!("cesco-gpt-{}.md", std::process::id()));
+    fs::write(&tmp_fn, draft)?;
+    // the editor command may contain arguments, e.g., "code --wait"
+    let mut words = editor.split_whitespace();
+    let prog = words.next().ok_or(anyhow!("Empty editor command."))?;
+    let status = Command::new(prog).args(words).arg(&tmp_fn).status()?;
+    let text = fs::read_to_string(&tmp_fn);
+    let _ = fs::remove_file(&tmp_fn);
+    if !status.success() {
+        return Err(anyhow!("Editor {} exited with {}", editor, status));
+    }
+    Ok(text?.trim_end().to_string())
+}
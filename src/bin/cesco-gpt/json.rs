@@ -0,0 +1,144 @@
+/**************************************************************************
+  Copyright 2024 Francesco Versaci (https://github.com/fversaci/)
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+**************************************************************************/
+
+//! Line protocol for driving a talk from other programs: one JSON
+//! request per line on stdin, one JSON event per line on stdout.
+
+use crate::send_msg;
+use anyhow::Result;
+use async_openai::types::{CreateRunRequest, CreateRunRequestArgs, RunCompletionUsage};
+use async_openai::{config::OpenAIConfig, Client};
+use cesco_gpt::talks::{stream_events, StreamEvent, Talk};
+use serde::{Deserialize, Serialize};
+use std::io::{stdout, Write};
+use tokio::io::{stdin, AsyncBufReadExt, BufReader};
+use tokio_stream::StreamExt;
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Request {
+    /// Send a message, wrapped in the talk delimiters
+    Message { text: String },
+    /// End the session and delete its thread
+    End,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Event {
+    SessionStarted {
+        talk: String,
+        thread_id: String,
+        greeting: Option<String>,
+    },
+    Delta {
+        text: String,
+    },
+    MessageCompleted {
+        text: String,
+    },
+    Usage {
+        #[serde(flatten)]
+        usage: RunCompletionUsage,
+    },
+    Error {
+        message: String,
+    },
+    SessionEnded {
+        thread_id: String,
+    },
+}
+
+fn emit(event: &Event) {
+    let mut lock = stdout().lock();
+    // events are always serializable, and a closed stdout ends the session anyway
+    if let Ok(line) = serde_json::to_string(event) {
+        let _ = writeln!(lock, "{}", line);
+        let _ = lock.flush();
+    }
+}
+
+fn emit_error(e: impl std::fmt::Display) {
+    emit(&Event::Error {
+        message: e.to_string(),
+    });
+}
+
+async fn do_request(
+    client: &Client<OpenAIConfig>,
+    thread_id: &str,
+    run_request: &CreateRunRequest,
+    msg: String,
+) -> Result<()> {
+    let run_stream = send_msg(client, thread_id, run_request, msg).await?;
+    let mut events = Box::pin(stream_events(run_stream));
+    let mut text = String::new();
+    while let Some(event) = events.next().await {
+        match event {
+            Ok(StreamEvent::Delta(delta)) => {
+                text.push_str(&delta);
+                emit(&Event::Delta { text: delta });
+            }
+            Ok(StreamEvent::Usage(usage)) => emit(&Event::Usage { usage }),
+            Err(e) => emit_error(e),
+        }
+    }
+    emit(&Event::MessageCompleted { text });
+    Ok(())
+}
+
+pub async fn run(client: &Client<OpenAIConfig>, talk: Talk) -> Result<()> {
+    let ts = match talk.get_conv(client).await {
+        Ok(ts) => ts,
+        Err(e) => {
+            emit_error(&e);
+            return Err(e);
+        }
+    };
+    let thread_id = ts.thread.id;
+    let (pre, suff) = ts.presuff;
+    let run_request = CreateRunRequestArgs::default()
+        .assistant_id(&ts.asst.id)
+        .parallel_tool_calls(false)
+        .build()?;
+    emit(&Event::SessionStarted {
+        talk: talk.to_string(),
+        thread_id: thread_id.clone(),
+        greeting: ts.msg,
+    });
+
+    let mut lines = BufReader::new(stdin()).lines();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<Request>(&line) {
+            Ok(Request::Message { text }) => {
+                let msg = format!("{pre}{text}\n{suff}");
+                if let Err(e) = do_request(client, &thread_id, &run_request, msg).await {
+                    emit_error(e);
+                }
+            }
+            Ok(Request::End) => break,
+            Err(e) => emit_error(format!("Invalid request: {e}")),
+        }
+    }
+    // clean up thread
+    client.threads().delete(&thread_id).await?;
+    emit(&Event::SessionEnded { thread_id });
+
+    Ok(())
+}
@@ -0,0 +1,144 @@
+/**************************************************************************
+  Copyright 2023 Francesco Versaci (https://github.com/fversaci/)
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+**************************************************************************/
+
+use anyhow::{anyhow, Result};
+use async_openai::types::{
+    AssistantEventStream, CreateMessageRequestArgs, CreateRunRequest, CreateRunRequestArgs,
+    MessageRole,
+};
+use async_openai::{config::OpenAIConfig, Client};
+use cesco_gpt::talks::{stream_messages, Talk};
+use clap::{Parser, Subcommand};
+use std::io::{stdout, Write};
+use tokio_stream::StreamExt;
+
+mod batch;
+mod editor;
+mod json;
+mod picker;
+mod session;
+mod tui;
+use editor::MsgReader;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Choose which conversation to start (interactively, if omitted)
+    #[command(subcommand)]
+    cmd: Option<Cmd>,
+    /// Emit JSON events on stdout and read JSON requests from stdin
+    #[arg(long, default_value_t = false)]
+    json: bool,
+    #[command(flatten)]
+    batch: batch::BatchArgs,
+}
+
+#[derive(Subcommand, Debug)]
+enum Cmd {
+    #[command(flatten)]
+    Talk(Talk),
+    /// Full-screen interface, with saved sessions
+    Tui {
+        #[command(subcommand)]
+        talk: Option<Talk>,
+    },
+}
+
+async fn print_stream(stream: AssistantEventStream) -> Result<()> {
+    let mut messages = Box::pin(stream_messages(stream));
+    let mut lock = stdout().lock();
+    while let Some(message) = messages.next().await {
+        match message {
+            Ok(text) => {
+                write!(lock, "{}", text).unwrap();
+                lock.flush().unwrap();
+            }
+            Err(e) => eprintln!("Error: {:?}", e),
+        }
+    }
+    writeln!(lock, "\n").unwrap();
+    Ok(())
+}
+
+/// Add a message to the thread and start a streamed run
+async fn send_msg(
+    client: &Client<OpenAIConfig>,
+    thread_id: &str,
+    run_request: &CreateRunRequest,
+    msg: String,
+) -> Result<AssistantEventStream> {
+    let message = CreateMessageRequestArgs::default()
+        .role(MessageRole::User)
+        .content(msg)
+        .build()?;
+    let _message_obj = client.threads().messages(thread_id).create(message).await?;
+    let run_stream = client
+        .threads()
+        .runs(thread_id)
+        .create_stream(run_request.clone())
+        .await?;
+    Ok(run_stream)
+}
+
+async fn run_repl(client: &Client<OpenAIConfig>, talk: Talk) -> Result<()> {
+    let mut reader = MsgReader::new(&talk)?;
+    let ts = talk.get_conv(client).await?;
+    let thread = ts.thread;
+    let asst = ts.asst;
+    let presuff = ts.presuff;
+    if let Some(msg) = ts.msg {
+        println!("{}\n", msg);
+    }
+    let run_request = CreateRunRequestArgs::default()
+        .assistant_id(&asst.id)
+        .parallel_tool_calls(false)
+        .build()?;
+
+    while let Some(msg) = reader.read_msg(&presuff) {
+        let run_stream = send_msg(client, &thread.id, &run_request, msg).await?;
+        print_stream(run_stream).await?;
+    }
+    // clean up thread
+    client.threads().delete(&thread.id).await?;
+
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+    let client = Client::new();
+    match args.cmd {
+        Some(Cmd::Tui { .. }) if args.batch.batch.is_some() => {
+            Err(anyhow!("Batch mode is not available in the TUI."))
+        }
+        Some(Cmd::Tui { talk }) => {
+            let talk = match talk {
+                Some(talk) => talk,
+                None => picker::pick_talk()?,
+            };
+            tui::run(&client, talk).await
+        }
+        Some(Cmd::Talk(talk)) if args.batch.batch.is_some() => {
+            batch::run(&client, talk, args.batch).await
+        }
+        _ if args.batch.batch.is_some() => Err(anyhow!("Batch mode requires the correct talk.")),
+        Some(Cmd::Talk(talk)) if args.json => json::run(&client, talk).await,
+        _ if args.json => Err(anyhow!("A conversation must be chosen in JSON mode.")),
+        Some(Cmd::Talk(talk)) => run_repl(&client, talk).await,
+        None => run_repl(&client, picker::pick_talk()?).await,
+    }
+}
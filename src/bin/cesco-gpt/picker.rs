@@ -0,0 +1,173 @@
+/**************************************************************************
+  Copyright 2024 Francesco Versaci (https://github.com/fversaci/)
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+**************************************************************************/
+
+//! Terminal menus to choose the talk, mirroring the ones of the Telegram bot
+
+use anyhow::Result;
+use cesco_gpt::talks::lang_practice::{Lang, LangLevel};
+use cesco_gpt::talks::Talk;
+use rustyline::DefaultEditor;
+use std::fmt::Display;
+use std::fs;
+use std::mem::discriminant;
+use std::path::PathBuf;
+use strum::IntoEnumIterator;
+
+/// Interactively choose the talk, using the last choice as default
+pub fn pick_talk() -> Result<Talk> {
+    let mut rl = DefaultEditor::new()?;
+    let last = load_last();
+    let talk = select_talk(&mut rl, last.as_ref())?;
+    // use the previous options as defaults only for the same kind of talk
+    let last = last.filter(|l| discriminant(l) == discriminant(&talk));
+    let talk = match talk {
+        Talk::Correct { .. } => choose_native(&mut rl, talk, last.as_ref())?,
+        Talk::LanguagePractice { .. } | Talk::Summarize { .. } => {
+            let talk = choose_lang(&mut rl, talk, last.as_ref())?;
+            choose_level(&mut rl, talk, last.as_ref())?
+        }
+        Talk::TranslateSubs { .. } => choose_lang(&mut rl, talk, last.as_ref())?,
+        // Talk::Generic
+        _ => talk,
+    };
+    save_last(&talk);
+    Ok(talk)
+}
+
+/// Print a numbered menu and return the chosen item (empty input selects the default)
+fn choose<T: Display + Clone>(
+    rl: &mut DefaultEditor,
+    txt_msg: &str,
+    items: &[T],
+    default: usize,
+) -> Result<T> {
+    println!("{txt_msg}");
+    for (i, item) in items.iter().enumerate() {
+        let mark = if i == default { "*" } else { " " };
+        println!("{mark} {}) {item}", i + 1);
+    }
+    loop {
+        let line = rl.readline(&format!("[{}]> ", default + 1))?;
+        let line = line.trim();
+        if line.is_empty() {
+            return Ok(items[default].clone());
+        }
+        // accept either the number or the name of the item
+        let by_num = line
+            .parse::<usize>()
+            .ok()
+            .and_then(|n| n.checked_sub(1))
+            .and_then(|n| items.get(n));
+        let by_name = || {
+            items
+                .iter()
+                .find(|item| item.to_string().eq_ignore_ascii_case(line))
+        };
+        match by_num.or_else(by_name) {
+            Some(item) => return Ok(item.clone()),
+            None => println!("Invalid choice: {line}"),
+        }
+    }
+}
+
+fn select_talk(rl: &mut DefaultEditor, last: Option<&Talk>) -> Result<Talk> {
+    let talks: Vec<Talk> = Talk::iter().collect();
+    let default = last
+        .and_then(|l| {
+            talks
+                .iter()
+                .position(|t| discriminant(t) == discriminant(l))
+        })
+        .unwrap_or_default();
+    choose(rl, "Choose the conversation:", &talks, default)
+}
+
+fn choose_native(rl: &mut DefaultEditor, mut talk: Talk, last: Option<&Talk>) -> Result<Talk> {
+    let yes_no = ["Yes", "No"];
+    let default = match last {
+        Some(Talk::Correct { native: false }) => 1,
+        _ => 0,
+    };
+    let new_nat = choose(rl, "Rephrase as a native speaker?", &yes_no, default)? == "Yes";
+    if let Talk::Correct { ref mut native, .. } = talk {
+        *native = new_nat;
+    }
+    Ok(talk)
+}
+
+fn choose_lang(rl: &mut DefaultEditor, mut talk: Talk, last: Option<&Talk>) -> Result<Talk> {
+    let langs: Vec<Lang> = Lang::iter().collect();
+    let last_lang = match last {
+        Some(Talk::LanguagePractice { lang, .. })
+        | Some(Talk::Summarize { lang, .. })
+        | Some(Talk::TranslateSubs { lang }) => Some(lang.to_string()),
+        _ => None,
+    };
+    let default = last_lang
+        .and_then(|l| langs.iter().position(|lang| lang.to_string() == l))
+        .unwrap_or_default();
+    let new_lang = choose(rl, "Choose the language:", &langs, default)?;
+    match talk {
+        Talk::LanguagePractice { ref mut lang, .. } => *lang = new_lang,
+        Talk::Summarize { ref mut lang, .. } => *lang = new_lang,
+        Talk::TranslateSubs { ref mut lang } => *lang = new_lang,
+        _ => (),
+    }
+    Ok(talk)
+}
+
+fn choose_level(rl: &mut DefaultEditor, mut talk: Talk, last: Option<&Talk>) -> Result<Talk> {
+    let levs: Vec<LangLevel> = LangLevel::iter().collect();
+    let last_lev = match last {
+        Some(Talk::LanguagePractice { level, .. }) | Some(Talk::Summarize { level, .. }) => {
+            Some(level.to_string())
+        }
+        _ => None,
+    };
+    let default = last_lev
+        .and_then(|l| levs.iter().position(|lev| lev.to_string() == l))
+        .unwrap_or_default();
+    let new_lev = choose(rl, "Choose your level:", &levs, default)?;
+    match talk {
+        Talk::LanguagePractice { ref mut level, .. } => *level = new_lev,
+        Talk::Summarize { ref mut level, .. } => *level = new_lev,
+        _ => (),
+    }
+    Ok(talk)
+}
+
+/// File storing the last chosen talk, e.g., `~/.config/cesco-gpt/last_talk.json`
+fn last_path() -> Option<PathBuf> {
+    Some(dirs::config_dir()?.join("cesco-gpt").join("last_talk.json"))
+}
+
+fn load_last() -> Option<Talk> {
+    let txt = fs::read_to_string(last_path()?).ok()?;
+    serde_json::from_str(&txt).ok()
+}
+
+fn save_last(talk: &Talk) {
+    let Some(fname) = last_path() else {
+        return;
+    };
+    let saved = fname
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(&fname, serde_json::to_string(talk).unwrap_or_default()));
+    if let Err(e) = saved {
+        eprintln!("Cannot save last choice to {}: {}", fname.display(), e);
+    }
+}
@@ -0,0 +1,137 @@
+/**************************************************************************
+  Copyright 2024 Francesco Versaci (https://github.com/fversaci/)
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+**************************************************************************/
+
+//! Conversations saved on disk, so that they can be resumed later
+
+use anyhow::{anyhow, Result};
+use async_openai::types::{CreateRunRequest, CreateRunRequestArgs, RunCompletionUsage};
+use cesco_gpt::talks::{Talk, TalkStart};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Role {
+    User,
+    Assistant,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Turn {
+    pub role: Role,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Usage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub total_tokens: u64,
+}
+
+impl Usage {
+    pub fn add(&mut self, usage: &RunCompletionUsage) {
+        self.prompt_tokens += usage.prompt_tokens as u64;
+        self.completion_tokens += usage.completion_tokens as u64;
+        self.total_tokens += usage.total_tokens as u64;
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    pub id: String,
+    pub talk: Talk,
+    pub thread_id: String,
+    pub asst_id: String,
+    pub presuff: (String, String),
+    pub turns: Vec<Turn>,
+    pub usage: Usage,
+}
+
+impl Session {
+    pub fn new(talk: Talk, ts: TalkStart) -> Self {
+        // also the file name: no colons, and unique within the same second
+        let time = Local::now().format("%Y-%m-%d_%H-%M-%S");
+        let thread = &ts.thread.id;
+        let suffix = &thread[thread.len().saturating_sub(6)..];
+        let id = format!("{time}_{suffix}");
+        let turns = ts
+            .msg
+            .into_iter()
+            .map(|text| Turn {
+                role: Role::Assistant,
+                text,
+            })
+            .collect();
+        Self {
+            id,
+            talk,
+            thread_id: ts.thread.id,
+            asst_id: ts.asst.id,
+            presuff: ts.presuff,
+            turns,
+            usage: Usage::default(),
+        }
+    }
+    pub fn run_request(&self) -> Result<CreateRunRequest> {
+        let run_request = CreateRunRequestArgs::default()
+            .assistant_id(&self.asst_id)
+            .parallel_tool_calls(false)
+            .build()?;
+        Ok(run_request)
+    }
+    pub fn save(&self) -> Result<()> {
+        let fname = session_path(&self.id)?;
+        fs::write(fname, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+    pub fn remove(&self) -> Result<()> {
+        fs::remove_file(session_path(&self.id)?)?;
+        Ok(())
+    }
+}
+
+/// Directory of the saved sessions, e.g., `~/.local/share/cesco-gpt/sessions/`
+fn sessions_dir() -> Result<PathBuf> {
+    let dir = dirs::data_dir()
+        .ok_or(anyhow!("Cannot find data directory."))?
+        .join("cesco-gpt")
+        .join("sessions");
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+fn session_path(id: &str) -> Result<PathBuf> {
+    Ok(sessions_dir()?.join(format!("{id}.json")))
+}
+
+/// All the saved sessions, newest first
+pub fn list_sessions() -> Result<Vec<Session>> {
+    let mut sessions = Vec::new();
+    for entry in fs::read_dir(sessions_dir()?)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "json") {
+            let txt = fs::read_to_string(&path)?;
+            match serde_json::from_str::<Session>(&txt) {
+                Ok(session) => sessions.push(session),
+                Err(e) => log::warn!("Skipping session {}: {}", path.display(), e),
+            }
+        }
+    }
+    sessions.sort_by(|a, b| b.id.cmp(&a.id));
+    Ok(sessions)
+}
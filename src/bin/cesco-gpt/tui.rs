@@ -0,0 +1,430 @@
+/**************************************************************************
+  Copyright 2024 Francesco Versaci (https://github.com/fversaci/)
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+**************************************************************************/
+
+//! Full-screen terminal interface, with saved sessions that can be resumed
+
+use crate::send_msg;
+use crate::session::{list_sessions, Role, Session, Turn};
+use anyhow::Result;
+use async_openai::types::RunCompletionUsage;
+use async_openai::{config::OpenAIConfig, Client};
+use cesco_gpt::talks::{stream_events, StreamEvent, Talk};
+use crossterm::event::{
+    DisableBracketedPaste, EnableBracketedPaste, Event, EventStream, KeyCode, KeyEvent,
+    KeyEventKind, KeyModifiers,
+};
+use crossterm::execute;
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::{Line, Text};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+use std::io::stdout;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio_stream::StreamExt;
+
+/// Messages sent to the UI by the background tasks
+enum AppMsg {
+    Delta(String),
+    Usage(RunCompletionUsage),
+    Error(String),
+    Done,
+    NewSession(Result<Box<Session>>),
+}
+
+#[derive(PartialEq)]
+enum Focus {
+    Input,
+    Sessions,
+}
+
+enum RunState {
+    Idle,
+    Starting,
+    Running,
+    Failed(String),
+}
+
+struct App {
+    client: Client<OpenAIConfig>,
+    current: Session,
+    sessions: Vec<Session>,
+    list_state: ListState,
+    input: String,
+    focus: Focus,
+    run_state: RunState,
+    /// A reply or a new session is pending, until its `Done` or
+    /// `NewSession` message: errors do not clear it
+    busy: bool,
+    /// lines scrolled up from the bottom of the conversation
+    scroll: u16,
+    tx: UnboundedSender<AppMsg>,
+    quit: bool,
+}
+
+/// Short description of the talk and its options, for the status bar
+fn talk_status(talk: &Talk) -> String {
+    match talk {
+        Talk::Generic => talk.to_string(),
+        Talk::LanguagePractice { lang, level } | Talk::Summarize { lang, level } => {
+            format!("{talk} | {level} {lang}")
+        }
+        Talk::Correct { native } => {
+            let native = if *native { "native" } else { "plain" };
+            format!("{talk} | {native}")
+        }
+        Talk::TranslateSubs { lang } => format!("{talk} | {lang}"),
+    }
+}
+
+impl App {
+    fn new(client: Client<OpenAIConfig>, current: Session, tx: UnboundedSender<AppMsg>) -> Self {
+        let mut app = Self {
+            client,
+            current,
+            sessions: Vec::new(),
+            list_state: ListState::default(),
+            input: String::new(),
+            focus: Focus::Input,
+            run_state: RunState::Idle,
+            busy: false,
+            scroll: 0,
+            tx,
+            quit: false,
+        };
+        app.refresh_sessions();
+        app
+    }
+    fn is_busy(&self) -> bool {
+        self.busy
+    }
+    fn save_current(&mut self) {
+        if let Err(e) = self.current.save() {
+            self.run_state = RunState::Failed(format!("Cannot save session: {e}"));
+        }
+    }
+    fn refresh_sessions(&mut self) {
+        match list_sessions() {
+            Ok(sessions) => self.sessions = sessions,
+            Err(e) => self.run_state = RunState::Failed(format!("Cannot list sessions: {e}")),
+        }
+        let pos = self.sessions.iter().position(|s| s.id == self.current.id);
+        self.list_state.select(pos.or(Some(0)));
+    }
+    fn send(&mut self) {
+        if self.is_busy() || self.input.trim().is_empty() {
+            return;
+        }
+        let text = std::mem::take(&mut self.input);
+        let (pre, suff) = &self.current.presuff;
+        let msg = format!("{pre}{text}\n{suff}");
+        self.current.turns.push(Turn {
+            role: Role::User,
+            text,
+        });
+        self.current.turns.push(Turn {
+            role: Role::Assistant,
+            text: String::new(),
+        });
+        self.run_state = RunState::Running;
+        self.busy = true;
+        self.scroll = 0;
+        let client = self.client.clone();
+        let thread_id = self.current.thread_id.clone();
+        let run_request = self.current.run_request();
+        let tx = self.tx.clone();
+        tokio::spawn(async move {
+            let run = async {
+                let run_stream = send_msg(&client, &thread_id, &run_request?, msg).await?;
+                let mut events = Box::pin(stream_events(run_stream));
+                while let Some(event) = events.next().await {
+                    let app_msg = match event {
+                        Ok(StreamEvent::Delta(delta)) => AppMsg::Delta(delta),
+                        Ok(StreamEvent::Usage(usage)) => AppMsg::Usage(usage),
+                        Err(e) => AppMsg::Error(e.to_string()),
+                    };
+                    let _ = tx.send(app_msg);
+                }
+                anyhow::Ok(())
+            };
+            if let Err(e) = run.await {
+                let _ = tx.send(AppMsg::Error(e.to_string()));
+            }
+            let _ = tx.send(AppMsg::Done);
+        });
+    }
+    fn new_session(&mut self) {
+        if self.is_busy() {
+            return;
+        }
+        self.run_state = RunState::Starting;
+        self.busy = true;
+        let client = self.client.clone();
+        let talk = self.current.talk.clone();
+        let tx = self.tx.clone();
+        tokio::spawn(async move {
+            let ts = talk.get_conv(&client).await;
+            let session = ts.map(|ts| Box::new(Session::new(talk, ts)));
+            let _ = tx.send(AppMsg::NewSession(session));
+        });
+    }
+    fn resume_selected(&mut self) {
+        if self.is_busy() {
+            return;
+        }
+        if let Some(session) = self
+            .list_state
+            .selected()
+            .and_then(|i| self.sessions.get(i))
+        {
+            self.current = session.clone();
+            self.scroll = 0;
+            self.focus = Focus::Input;
+        }
+    }
+    fn delete_selected(&mut self) {
+        let Some(i) = self.list_state.selected() else {
+            return;
+        };
+        let Some(session) = self.sessions.get(i) else {
+            return;
+        };
+        if session.id == self.current.id {
+            self.run_state = RunState::Failed("Cannot delete the current session.".to_string());
+            return;
+        }
+        if let Err(e) = session.remove() {
+            self.run_state = RunState::Failed(format!("Cannot delete session: {e}"));
+        }
+        // clean up thread
+        let client = self.client.clone();
+        let thread_id = session.thread_id.clone();
+        tokio::spawn(async move { client.threads().delete(&thread_id).await });
+        self.refresh_sessions();
+    }
+    fn handle_msg(&mut self, msg: AppMsg) {
+        match msg {
+            AppMsg::Delta(delta) => {
+                if let Some(turn) = self.current.turns.last_mut() {
+                    turn.text.push_str(&delta);
+                }
+            }
+            AppMsg::Usage(usage) => self.current.usage.add(&usage),
+            AppMsg::Error(e) => self.run_state = RunState::Failed(e),
+            AppMsg::Done => {
+                self.busy = false;
+                if let RunState::Running = self.run_state {
+                    self.run_state = RunState::Idle;
+                }
+                self.save_current();
+                self.refresh_sessions();
+            }
+            AppMsg::NewSession(Ok(session)) => {
+                self.busy = false;
+                self.current = *session;
+                self.run_state = RunState::Idle;
+                self.scroll = 0;
+                self.focus = Focus::Input;
+                self.save_current();
+                self.refresh_sessions();
+            }
+            AppMsg::NewSession(Err(e)) => {
+                self.busy = false;
+                self.run_state = RunState::Failed(e.to_string());
+            }
+        }
+    }
+    fn handle_key(&mut self, key: KeyEvent) {
+        if key.kind != KeyEventKind::Press {
+            return;
+        }
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        let alt = key.modifiers.contains(KeyModifiers::ALT);
+        match key.code {
+            KeyCode::Char('c') | KeyCode::Char('q') if ctrl => self.quit = true,
+            KeyCode::Char('n') if ctrl => self.new_session(),
+            KeyCode::Tab => {
+                self.focus = match self.focus {
+                    Focus::Input => Focus::Sessions,
+                    Focus::Sessions => Focus::Input,
+                }
+            }
+            KeyCode::PageUp => self.scroll = self.scroll.saturating_add(10),
+            KeyCode::PageDown => self.scroll = self.scroll.saturating_sub(10),
+            _ if self.focus == Focus::Sessions => match key.code {
+                KeyCode::Up => self.list_state.select_previous(),
+                KeyCode::Down => self.list_state.select_next(),
+                KeyCode::Enter => self.resume_selected(),
+                KeyCode::Delete | KeyCode::Char('d') => self.delete_selected(),
+                KeyCode::Esc => self.focus = Focus::Input,
+                _ => {}
+            },
+            KeyCode::Enter if alt => self.input.push('\n'),
+            KeyCode::Char('j') if ctrl => self.input.push('\n'),
+            KeyCode::Enter => self.send(),
+            KeyCode::Backspace => {
+                self.input.pop();
+            }
+            KeyCode::Char(c) if !ctrl => self.input.push(c),
+            _ => {}
+        }
+    }
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, status] =
+            Layout::vertical([Constraint::Min(5), Constraint::Length(1)]).areas(frame.area());
+        let [side, body] =
+            Layout::horizontal([Constraint::Length(28), Constraint::Min(20)]).areas(main);
+        let input_height = (self.input.lines().count() as u16).clamp(1, 8) + 2;
+        let [conv, input] =
+            Layout::vertical([Constraint::Min(3), Constraint::Length(input_height)]).areas(body);
+        self.draw_sessions(frame, side);
+        self.draw_conversation(frame, conv);
+        self.draw_input(frame, input);
+        self.draw_status(frame, status);
+    }
+    fn focus_style(&self, focus: Focus) -> Style {
+        if self.focus == focus {
+            Style::default().fg(Color::Yellow)
+        } else {
+            Style::default()
+        }
+    }
+    fn draw_sessions(&mut self, frame: &mut Frame, area: Rect) {
+        let items: Vec<ListItem> = self
+            .sessions
+            .iter()
+            .map(|s| {
+                let mark = if s.id == self.current.id { "*" } else { " " };
+                ListItem::new(vec![
+                    Line::from(format!("{mark}{}", s.id)),
+                    Line::from(format!("  {}", s.talk)).dim(),
+                ])
+            })
+            .collect();
+        let list = List::new(items)
+            .block(
+                Block::bordered()
+                    .title("Sessions")
+                    .border_style(self.focus_style(Focus::Sessions)),
+            )
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, area, &mut self.list_state);
+    }
+    fn draw_conversation(&mut self, frame: &mut Frame, area: Rect) {
+        let mut text = Text::default();
+        for turn in &self.current.turns {
+            let (name, style) = match turn.role {
+                Role::User => ("You", Style::default().fg(Color::Cyan).bold()),
+                Role::Assistant => ("CescoGPT", Style::default().fg(Color::Green).bold()),
+            };
+            text.push_line(Line::styled(name, style));
+            for line in turn.text.lines() {
+                text.push_line(Line::raw(line.to_string()));
+            }
+            text.push_line(Line::default());
+        }
+        let par = Paragraph::new(text)
+            .block(Block::bordered().title(self.current.talk.to_string()))
+            .wrap(Wrap { trim: false });
+        // keep the end of the conversation in view, unless scrolled up
+        let height = area.height.saturating_sub(2) as usize;
+        let num_lines = par.line_count(area.width.saturating_sub(2));
+        let max_scroll = num_lines.saturating_sub(height) as u16;
+        self.scroll = self.scroll.min(max_scroll);
+        let par = par.scroll((max_scroll - self.scroll, 0));
+        frame.render_widget(par, area);
+    }
+    fn draw_input(&self, frame: &mut Frame, area: Rect) {
+        let title = "Message (enter: send, alt+enter: new line)";
+        let lines: Vec<&str> = self.input.split('\n').collect();
+        // show only the last lines if the input is taller than the box
+        let height = area.height.saturating_sub(2) as usize;
+        let skip = lines.len().saturating_sub(height);
+        let par = Paragraph::new(lines[skip..].join("\n")).block(
+            Block::bordered()
+                .title(title)
+                .border_style(self.focus_style(Focus::Input)),
+        );
+        frame.render_widget(par, area);
+        if self.focus == Focus::Input {
+            let last = lines.last().map_or(0, |l| l.chars().count()) as u16;
+            let row = (lines.len() - skip) as u16;
+            frame.set_cursor_position((area.x + 1 + last, area.y + row));
+        }
+    }
+    fn draw_status(&self, frame: &mut Frame, area: Rect) {
+        let run = match &self.run_state {
+            RunState::Idle => "idle".to_string(),
+            RunState::Starting => "starting...".to_string(),
+            RunState::Running => "running...".to_string(),
+            RunState::Failed(e) => format!("error: {e}"),
+        };
+        let usage = &self.current.usage;
+        let status = format!(
+            " {} | tokens: {} in, {} out | {} | tab: switch panel, ctrl+n: new, ctrl+q: quit",
+            talk_status(&self.current.talk),
+            usage.prompt_tokens,
+            usage.completion_tokens,
+            run
+        );
+        let style = match self.run_state {
+            RunState::Failed(_) => Style::default().bg(Color::Red),
+            _ => Style::default().reversed(),
+        };
+        frame.render_widget(Paragraph::new(status).style(style), area);
+    }
+}
+
+async fn event_loop(
+    terminal: &mut DefaultTerminal,
+    app: &mut App,
+    rx: &mut UnboundedReceiver<AppMsg>,
+) -> Result<()> {
+    let mut events = EventStream::new();
+    while !app.quit {
+        terminal.draw(|frame| app.draw(frame))?;
+        tokio::select! {
+            event = events.next() => match event {
+                Some(Ok(Event::Key(key))) => app.handle_key(key),
+                // pasted texts are kept intact, including paragraph breaks
+                Some(Ok(Event::Paste(text))) => {
+                    let text = text.replace("\r\n", "\n").replace('\r', "\n");
+                    app.input.push_str(&text);
+                }
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Err(e.into()),
+                None => break,
+            },
+            Some(msg) = rx.recv() => app.handle_msg(msg),
+        }
+    }
+    Ok(())
+}
+
+pub async fn run(client: &Client<OpenAIConfig>, talk: Talk) -> Result<()> {
+    let ts = talk.get_conv(client).await?;
+    let session = Session::new(talk, ts);
+    session.save()?;
+    let (tx, mut rx) = unbounded_channel();
+    let mut app = App::new(client.clone(), session, tx);
+    let mut terminal = ratatui::try_init()?;
+    execute!(stdout(), EnableBracketedPaste)?;
+    let res = event_loop(&mut terminal, &mut app, &mut rx).await;
+    execute!(stdout(), DisableBracketedPaste)?;
+    ratatui::restore();
+    // threads are kept, so that the sessions can be resumed later
+    res
+}
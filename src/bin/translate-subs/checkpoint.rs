@@ -0,0 +1,140 @@
+/**************************************************************************
+  Copyright 2024 Francesco Versaci (https://github.com/fversaci/)
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+**************************************************************************/
+
+//! Sidecar file recording the translated chunks, to resume interrupted runs
+
+use crate::subs::Cue;
+use crate::with_ext;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// A translated chunk, covering the cues `start..end` of the input
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChunkRecord {
+    pub start: usize,
+    pub end: usize,
+    /// Hash of the source cues and of the target language
+    pub hash: String,
+    /// Translated text of each source cue
+    pub texts: Vec<Vec<String>>,
+}
+
+impl ChunkRecord {
+    pub fn new(start: usize, hash: String, trans: &[Cue]) -> Self {
+        Self {
+            start,
+            end: start + trans.len(),
+            hash,
+            texts: trans.iter().map(|cue| cue.text.clone()).collect(),
+        }
+    }
+    /// Rebuild the translated cues from the source ones
+    pub fn to_cues(&self, chunk: &[Cue]) -> Vec<Cue> {
+        chunk
+            .iter()
+            .zip(&self.texts)
+            .map(|(cue, text)| Cue {
+                text: text.clone(),
+                ..cue.clone()
+            })
+            .collect()
+    }
+}
+
+/// Initial value of the FNV-1a hashes
+pub const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+
+/// 64-bit FNV-1a, stable across runs and platforms
+pub fn fnv1a(data: &[u8], mut hash: u64) -> u64 {
+    for byte in data {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+pub fn source_hash(chunk: &[Cue], lang: &str) -> String {
+    let mut hash = fnv1a(lang.as_bytes(), FNV_OFFSET);
+    for cue in chunk {
+        let times = format!("{}-{}", cue.start.as_millis(), cue.end.as_millis());
+        hash = fnv1a(times.as_bytes(), hash);
+        for line in &cue.text {
+            hash = fnv1a(line.as_bytes(), hash);
+            hash = fnv1a(b"\n", hash);
+        }
+    }
+    format!("{hash:016x}")
+}
+
+pub struct Checkpoint {
+    fname: PathBuf,
+    file: Mutex<File>,
+    done: HashMap<(usize, usize), ChunkRecord>,
+}
+
+impl Checkpoint {
+    /// Open the checkpoint, loading the completed chunks if resuming
+    pub fn open(out_fname: &Path, resume: bool) -> Result<Self> {
+        // e.g., `movie.eng.srt.ckpt`
+        let fname = with_ext(out_fname, "ckpt");
+        let mut done = HashMap::new();
+        if resume && fname.exists() {
+            let reader = BufReader::new(File::open(&fname)?);
+            for line in reader.lines() {
+                // the last line may be truncated, if the run was killed while writing it
+                if let Ok(rec) = serde_json::from_str::<ChunkRecord>(&line?) {
+                    done.insert((rec.start, rec.end), rec);
+                }
+            }
+            println!("Resuming: {} chunks already translated", done.len());
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(resume)
+            .write(true)
+            .truncate(!resume)
+            .open(&fname)?;
+        Ok(Self {
+            fname,
+            file: Mutex::new(file),
+            done,
+        })
+    }
+    /// Translation of the chunk, if it was completed in a previous run
+    pub fn get(&self, start: usize, chunk: &[Cue], hash: &str) -> Option<Vec<Cue>> {
+        self.done
+            .get(&(start, start + chunk.len()))
+            .filter(|rec| rec.hash == hash && rec.texts.len() == chunk.len())
+            .map(|rec| rec.to_cues(chunk))
+    }
+    pub fn save(&self, rec: &ChunkRecord) -> Result<()> {
+        let line = serde_json::to_string(rec)?;
+        let mut file = self.file.lock().unwrap();
+        writeln!(file, "{}", line)?;
+        file.flush()?;
+        Ok(())
+    }
+    /// Remove the checkpoint, once the output has been written
+    pub fn remove(self) -> Result<()> {
+        fs::remove_file(&self.fname)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,118 @@
+/**************************************************************************
+  Copyright 2024 Francesco Versaci (https://github.com/fversaci/)
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+**************************************************************************/
+
+//! Division of the subtitles into chunks, sized by their estimated tokens
+
+use crate::subs::Cue;
+use anyhow::Result;
+use std::time::Duration;
+use tiktoken_rs::CoreBPE;
+
+/// Gap between cues which likely marks a scene change
+const SCENE_GAP: Duration = Duration::from_secs(2);
+/// Tokens of the JSON around each cue (label, quotes, brackets), and around each line
+const CUE_OVERHEAD: usize = 8;
+const LINE_OVERHEAD: usize = 2;
+
+fn is_end_of_sentence(character: &char) -> bool {
+    let sentence_terminators = &[
+        '.', '!', '?', ';', ':', '…', '؟', '।', '॥', '♪', '*', '"', '>', '。', '｡', '．', '？',
+        '！', '；', '：', '」', '』', '）',
+    ];
+    sentence_terminators.contains(character)
+}
+
+pub struct Chunker {
+    bpe: CoreBPE,
+    max_cues: usize,
+    max_tokens: usize,
+}
+
+impl Chunker {
+    pub fn new(max_cues: usize, max_tokens: usize) -> Result<Self> {
+        Ok(Self {
+            bpe: tiktoken_rs::o200k_base()?,
+            max_cues: max_cues.max(1),
+            max_tokens,
+        })
+    }
+    /// Number of tokens of the text
+    pub fn count(&self, text: &str) -> usize {
+        self.bpe.encode_ordinary(text).len()
+    }
+    /// Estimated tokens of the cue, as sent in a JSON chunk
+    pub fn tokens(&self, cue: &Cue) -> usize {
+        let text: usize = cue.text.iter().map(|l| self.count(l) + LINE_OVERHEAD).sum();
+        text + CUE_OVERHEAD
+    }
+    /// Divide in chunks within the budgets of cues and tokens, preferably
+    /// splitting at long gaps (e.g., scene changes) and at end of sentence
+    pub fn split<'a>(&self, subs: &'a [Cue]) -> Vec<&'a [Cue]> {
+        let tokens: Vec<usize> = subs.iter().map(|c| self.tokens(c)).collect();
+        let mut ret = Vec::new();
+        let mut beg = 0;
+        while beg < subs.len() {
+            // longest chunk within the budgets, with at least a cue
+            let mut end = beg + 1;
+            let mut used = tokens[beg];
+            while end < subs.len() && end - beg < self.max_cues {
+                if used + tokens[end] > self.max_tokens {
+                    break;
+                }
+                used += tokens[end];
+                end += 1;
+            }
+            if end < subs.len() {
+                // choose the best cut in the second half of the chunk
+                let min_end = beg + (end - beg).div_ceil(2);
+                let best = (min_end..=end)
+                    .max_by(|&a, &b| {
+                        let score = |cut| self.cut_score(subs, beg, cut, end);
+                        score(a).total_cmp(&score(b))
+                    })
+                    .unwrap_or(end);
+                if self.cut_score(subs, beg, best, end) < 1.0 {
+                    println!(
+                        "Cannot split at end-of-sentence or pause: chunk {}-{}",
+                        subs[beg].sequence,
+                        subs[best - 1].sequence
+                    );
+                }
+                end = best;
+            }
+            ret.push(&subs[beg..end]);
+            beg = end;
+        }
+        ret
+    }
+    /// Preference for ending the chunk `beg..cut`, which can be at most `beg..max_end`
+    fn cut_score(&self, subs: &[Cue], beg: usize, cut: usize, max_end: usize) -> f64 {
+        let last = &subs[cut - 1];
+        let eos = last.text.last().and_then(|l| l.trim_end().chars().last());
+        let eos = match eos {
+            Some(c) if is_end_of_sentence(&c) => 1.0,
+            _ => 0.0,
+        };
+        let gap = match subs.get(cut) {
+            Some(next) => next.start.saturating_sub(last.end),
+            None => SCENE_GAP,
+        };
+        let gap = (gap.as_secs_f64() / SCENE_GAP.as_secs_f64()).min(1.0);
+        // slightly prefer longer chunks, for fewer calls
+        let len = (cut - beg) as f64 / (max_end - beg) as f64;
+        eos + 2.0 * gap + 0.5 * len
+    }
+}
@@ -0,0 +1,98 @@
+/**************************************************************************
+  Copyright 2024 Francesco Versaci (https://github.com/fversaci/)
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+**************************************************************************/
+
+//! Dialogue surrounding a chunk, sent as read-only context of its translation
+
+use crate::subs::{plain_text, Cue};
+use std::sync::Mutex;
+
+pub struct Context {
+    /// Number of cues before and after the chunk
+    size: usize,
+    source: Vec<Cue>,
+    /// Accepted translation of each source cue, once available
+    trans: Mutex<Vec<Option<Vec<String>>>>,
+}
+
+impl Context {
+    pub fn new(source: &[Cue], size: usize) -> Self {
+        Self {
+            size,
+            source: source.to_vec(),
+            trans: Mutex::new(vec![None; source.len()]),
+        }
+    }
+    /// Record the translation of the cues starting at `start`
+    pub fn accept(&self, start: usize, trans: &[Cue]) {
+        let mut all = self.trans.lock().unwrap();
+        for (slot, cue) in all.iter_mut().skip(start).zip(trans) {
+            *slot = Some(cue.text.clone());
+        }
+    }
+    /// Context instructions for the cues with the given indices, if any,
+    /// including the ones between them which are not to be translated
+    /// (e.g., found in the translation memory).
+    ///
+    /// Since chunks are translated in parallel, the translation of the
+    /// previous cues is included only when already available.
+    pub fn instructions(&self, idxs: &[usize]) -> Option<String> {
+        let (&start, &last) = (idxs.first()?, idxs.last()?);
+        let end = (last + 1).min(self.source.len());
+        let prev = start.saturating_sub(self.size)..start;
+        let inside: Vec<usize> = (start..end).filter(|i| !idxs.contains(i)).collect();
+        let next = end..(end + self.size).min(self.source.len());
+        if prev.is_empty() && next.is_empty() && inside.is_empty() {
+            return None;
+        }
+        let trans = self.trans.lock().unwrap();
+        // formatting tags are left out, not to be copied into the translation
+        let line = |text: &[String]| {
+            let text: Vec<String> = text.iter().map(|l| plain_text(l)).collect();
+            text.join(" ")
+        };
+        let with_trans = |instr: &mut String, i: usize| match &trans[i] {
+            Some(t) => instr.push_str(&format!(
+                "- {} => {}\n",
+                line(&self.source[i].text),
+                line(t)
+            )),
+            None => instr.push_str(&format!("- {}\n", line(&self.source[i].text))),
+        };
+        let mut instr = "The following cues are only context for the dialogue: \
+                         do not translate them and do not include them in your output.\n"
+            .to_string();
+        if !prev.is_empty() {
+            instr.push_str("Previous cues (with their translation, when available):\n");
+            for i in prev {
+                with_trans(&mut instr, i);
+            }
+        }
+        if !inside.is_empty() {
+            instr.push_str("Cues already translated, among the ones to be translated:\n");
+            for i in inside {
+                with_trans(&mut instr, i);
+            }
+        }
+        if !next.is_empty() {
+            instr.push_str("Next cues:\n");
+            for cue in &self.source[next] {
+                instr.push_str(&format!("- {}\n", line(&cue.text)));
+            }
+        }
+        instr.push_str("Subtitles to be translated:\n");
+        Some(instr)
+    }
+}
@@ -0,0 +1,120 @@
+/**************************************************************************
+  Copyright 2024 Francesco Versaci (https://github.com/fversaci/)
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+**************************************************************************/
+
+//! Preview of the chunks and estimate of the cost, without contacting the API
+
+use crate::chunker::Chunker;
+use crate::context::Context;
+use crate::glossary::Glossary;
+use crate::report::timestamp;
+use crate::sdh::{self, Sdh};
+use crate::subs::Cue;
+use crate::tags::Masks;
+use crate::translator::command;
+use cesco_gpt::talks::lang_practice::{Lang, LangLevel};
+
+/// List prices in USD per million input and output tokens, which may be outdated
+const PRICES: &[(&str, f64, f64)] = &[
+    ("gpt-4o-mini", 0.15, 0.60),
+    ("gpt-4o", 2.50, 10.00),
+    ("gpt-4-turbo", 10.00, 30.00),
+    ("gpt-3.5-turbo", 0.50, 1.50),
+];
+/// Tokens of the instructions of the Translate Subtitles assistant
+const ASSISTANT_TOKENS: usize = 300;
+/// Tokens of the system message and of the schema of each cue, with --structured
+const SYSTEM_TOKENS: usize = 40;
+const SCHEMA_TOKENS: usize = 15;
+/// Context window of the assistant threads, beyond which the oldest messages are dropped
+const MAX_THREAD: usize = 128_000;
+/// Ratio between the tokens of the translated chunk and of the source one
+const OUTPUT_RATIO: f64 = 1.2;
+
+/// Settings of the translation which affect its cost
+pub struct Plan<'a> {
+    pub langs: &'a [Lang],
+    pub level: Option<&'a LangLevel>,
+    pub sdh: Sdh,
+    /// Glossary of each language
+    pub glossaries: &'a [Glossary],
+    pub context: usize,
+    pub num: usize,
+    pub structured: bool,
+}
+
+/// Print the chunks to be sent and the estimated tokens and cost
+pub fn run(chunker: &Chunker, source: &[Cue], chunks: &[(usize, &[Cue])], plan: &Plan) {
+    let sizes: Vec<usize> = chunks
+        .iter()
+        .map(|(_, chunk)| chunk.iter().map(|c| chunker.tokens(c)).sum())
+        .collect();
+    for (i, ((_, chunk), size)) in chunks.iter().zip(&sizes).enumerate() {
+        let (Some(first), Some(last)) = (chunk.first(), chunk.last()) else {
+            continue;
+        };
+        println!(
+            "Chunk {}: blocks {}-{} [{} - {}], {} cues, {} tokens",
+            i + 1,
+            first.sequence,
+            last.sequence,
+            timestamp(first.start),
+            timestamp(last.end),
+            chunk.len(),
+            size
+        );
+    }
+    let (mut input, mut output) = (0, 0);
+    for (lang, glossary) in plan.langs.iter().zip(plan.glossaries) {
+        let context = Context::new(source, plan.context);
+        // each translator resends the history of its thread
+        let mut history = vec![0; plan.num.max(1)];
+        let (mut lang_in, mut lang_out) = (0, 0);
+        for (i, ((start, chunk), size)) in chunks.iter().zip(&sizes).enumerate() {
+            let (masks, _) = Masks::new(chunk, plan.sdh == Sdh::Keep);
+            let mut instr = command(lang, plan.level);
+            instr.push_str(&glossary.instructions(chunk).unwrap_or_default());
+            instr.push_str(&masks.instructions().unwrap_or_default());
+            if plan.sdh == Sdh::Translate {
+                instr.push_str(&sdh::instructions(chunk).unwrap_or_default());
+            }
+            let idxs: Vec<usize> = (*start..start + chunk.len()).collect();
+            instr.push_str(&context.instructions(&idxs).unwrap_or_default());
+            let prompt = chunker.count(&instr) + size;
+            let reply = (*size as f64 * OUTPUT_RATIO) as usize;
+            lang_in += match plan.structured {
+                true => SYSTEM_TOKENS + SCHEMA_TOKENS * chunk.len() + prompt,
+                false => {
+                    let thread = &mut history[i % plan.num.max(1)];
+                    let sent = ASSISTANT_TOKENS + (*thread).min(MAX_THREAD) + prompt;
+                    *thread += prompt + reply;
+                    sent
+                }
+            };
+            lang_out += reply;
+        }
+        println!(
+            "{}: about {} input and {} output tokens",
+            lang, lang_in, lang_out
+        );
+        input += lang_in;
+        output += lang_out;
+    }
+    println!("Estimated cost (list prices in USD, which may be outdated, without retries):");
+    for (model, price_in, price_out) in PRICES {
+        let cost = (input as f64 * price_in + output as f64 * price_out) / 1e6;
+        println!("  {model}: ${cost:.2}");
+    }
+}
@@ -0,0 +1,83 @@
+/**************************************************************************
+  Copyright 2024 Francesco Versaci (https://github.com/fversaci/)
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+**************************************************************************/
+
+//! Bilingual output, with both the original text and the translation
+
+use crate::subs::{Cue, SubFormat, Subtitles};
+use anyhow::Result;
+use clap::ValueEnum;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Dual {
+    /// Original lines stacked above the translated ones, in each cue
+    Stacked,
+    /// Two styled ASS/SSA tracks, the original at the top of the screen
+    Tracks,
+    /// The original subtitles in a separate file, with the same numbering
+    Separate,
+}
+
+/// Output file of the original subtitles, e.g., `movie.eng.source.srt`
+fn source_path(out_fname: &Path) -> PathBuf {
+    let stem = out_fname.file_stem().unwrap_or_default().to_string_lossy();
+    let fname = match out_fname.extension() {
+        Some(ext) => format!("{}.source.{}", stem, ext.to_string_lossy()),
+        None => format!("{}.source", stem),
+    };
+    out_fname.with_file_name(fname)
+}
+
+pub fn write(
+    dual: Dual,
+    trans: &Subtitles,
+    source: &[Cue],
+    out_fname: &Path,
+    format: SubFormat,
+) -> Result<()> {
+    match dual {
+        Dual::Stacked => {
+            let mut subs = trans.clone();
+            for (t, s) in subs.cues.iter_mut().zip(source) {
+                t.text = s.text.iter().chain(&t.text).cloned().collect();
+            }
+            fs::write(out_fname, subs.render(format))?;
+        }
+        Dual::Tracks => {
+            let subs = trans.with_source_track(source, format)?;
+            fs::write(out_fname, subs.render(format))?;
+        }
+        Dual::Separate => {
+            fs::write(out_fname, trans.render(format))?;
+            // same cues as the translation, which drops the empty ones
+            let cues = source
+                .iter()
+                .zip(&trans.cues)
+                .filter(|(_, t)| !t.text.is_empty())
+                .map(|(s, _)| s.clone())
+                .collect();
+            let subs = Subtitles {
+                cues,
+                ..trans.clone()
+            };
+            let source_fname = source_path(out_fname);
+            fs::write(&source_fname, subs.render(format))?;
+            println!("Original subtitles saved in {}", source_fname.display());
+        }
+    }
+    Ok(())
+}
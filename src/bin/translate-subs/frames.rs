@@ -0,0 +1,159 @@
+/**************************************************************************
+  Copyright 2024 Francesco Versaci (https://github.com/fversaci/)
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+**************************************************************************/
+
+//! Distribution of a translation merging several cues among their frames
+
+use crate::segment::{self, Word};
+use crate::subs::{plain_text, Cue};
+
+/// A piece of text which cannot be split, e.g., a word
+struct Token {
+    word: Word,
+    /// Whether the token starts a new line of the translation
+    new_line: bool,
+}
+
+fn tokenize(trans_text: &[String]) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    for line in trans_text {
+        for (i, word) in segment::words(line).into_iter().enumerate() {
+            tokens.push(Token {
+                word,
+                new_line: i == 0,
+            });
+        }
+    }
+    tokens
+}
+
+/// Share of the text due to each frame, from its duration and source length
+fn frame_weights(in_blocks: &[Cue]) -> Vec<f64> {
+    let durs: Vec<f64> = in_blocks
+        .iter()
+        .map(|c| c.end.saturating_sub(c.start).as_secs_f64().max(0.001))
+        .collect();
+    let lens: Vec<f64> = in_blocks
+        .iter()
+        .map(|c| {
+            c.text
+                .iter()
+                .map(|l| plain_text(l).chars().count())
+                .sum::<usize>() as f64
+        })
+        .map(|len| len.max(1.0))
+        .collect();
+    let (tot_dur, tot_len): (f64, f64) = (durs.iter().sum(), lens.iter().sum());
+    durs.iter()
+        .zip(&lens)
+        .map(|(d, l)| 0.5 * d / tot_dur + 0.5 * l / tot_len)
+        .collect()
+}
+
+/// Preference for cutting before the given token: line breaks first, then punctuation
+fn cut_bonus(tokens: &[Token], idx: usize) -> f64 {
+    if tokens[idx].new_line {
+        return 1.0;
+    }
+    let prev = plain_text(&tokens[idx - 1].word.text);
+    if prev.ends_with(['.', '!', '?', '…', '。', '！', '？']) {
+        0.8
+    } else if prev.ends_with([',', ';', ':', '，', '、', '；', '：']) {
+        0.5
+    } else {
+        0.0
+    }
+}
+
+/// Split the translated text among the frames, in proportion to their
+/// weights, cutting at line breaks or punctuation when close enough
+fn split_into_frames(trans_text: &[String], in_blocks: &[Cue]) -> Vec<Vec<String>> {
+    let num_frames = in_blocks.len();
+    // single frame, return text as vector
+    if num_frames == 1 {
+        return vec![trans_text.to_owned()];
+    }
+    let tokens = tokenize(trans_text);
+    let weights = frame_weights(in_blocks);
+    // too few words for all the frames: show them only in the heaviest one,
+    // leaving the others empty instead of repeating the text
+    if tokens.len() < num_frames {
+        let heaviest = (0..num_frames)
+            .max_by(|&a, &b| weights[a].total_cmp(&weights[b]))
+            .unwrap_or(0);
+        let mut frames = vec![Vec::new(); num_frames];
+        frames[heaviest] = trans_text.to_owned();
+        return frames;
+    }
+    // character offset of the start of each token
+    let mut offsets = Vec::with_capacity(tokens.len() + 1);
+    let mut pos = 0.0;
+    for tok in &tokens {
+        offsets.push(pos);
+        let space = if tok.word.space_before { 1.0 } else { 0.0 };
+        pos += plain_text(&tok.word.text).chars().count() as f64 + space;
+    }
+    let total = pos;
+    offsets.push(total);
+    // choose the cut before the token closest to each target offset
+    let avg_frame = total / num_frames as f64;
+    let mut cuts = vec![0];
+    let mut target = 0.0;
+    for (k, w) in weights.iter().enumerate().take(num_frames - 1) {
+        target += w * total;
+        let prev = *cuts.last().unwrap();
+        // leave at least a token to each remaining frame
+        let max_cut = tokens.len() - (num_frames - k - 1);
+        let best = (prev + 1..=max_cut).min_by(|&a, &b| {
+            let cost = |i: usize| {
+                let bonus = if i < tokens.len() {
+                    cut_bonus(&tokens, i)
+                } else {
+                    0.0
+                };
+                (offsets[i] - target).abs() / avg_frame - 0.3 * bonus
+            };
+            cost(a).total_cmp(&cost(b))
+        });
+        cuts.push(best.unwrap_or(prev));
+    }
+    cuts.push(tokens.len());
+    cuts.windows(2)
+        .map(|w| {
+            let mut lines: Vec<Vec<Word>> = Vec::new();
+            for tok in &tokens[w[0]..w[1]] {
+                match lines.last_mut() {
+                    Some(line) if !tok.new_line => line.push(tok.word.clone()),
+                    _ => lines.push(vec![tok.word.clone()]),
+                }
+            }
+            lines.iter().map(|line| segment::join(line)).collect()
+        })
+        .collect()
+}
+
+/// Translated cues, sharing the text of a block merging the input ones.
+/// Some of them are left empty when the text is too short to be shared.
+pub fn assemble_blocks(in_blocks: &[Cue], trans_text: &[String]) -> Vec<Cue> {
+    let frames = split_into_frames(trans_text, in_blocks);
+    in_blocks
+        .iter()
+        .zip(frames)
+        .map(|(in_block, text)| Cue {
+            text,
+            ..in_block.clone()
+        })
+        .collect()
+}
@@ -0,0 +1,238 @@
+/**************************************************************************
+  Copyright 2024 Francesco Versaci (https://github.com/fversaci/)
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+**************************************************************************/
+
+//! Glossary of names and terms, to be translated consistently across chunks
+
+use crate::helper::{find_json, Helper};
+use crate::subs::Cue;
+use crate::with_iso;
+use anyhow::{anyhow, Result};
+use async_openai::{config::OpenAIConfig, Client};
+use cesco_gpt::talks::lang_practice::Lang;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// Number of cues sent in each name extraction query
+const EXTRACT_CUES: usize = 400;
+
+#[derive(Debug, Clone)]
+pub struct Term {
+    pub source: String,
+    /// Same as the source, for terms which must not be translated
+    pub target: String,
+}
+
+impl Term {
+    fn keep(&self) -> bool {
+        self.source == self.target
+    }
+}
+
+/// TOML glossary, e.g.:
+/// ```toml
+/// keep = ["Hogwarts", "Muggle"]
+/// [terms]
+/// "Winterfell" = "Grande Inverno"
+/// ```
+#[derive(Deserialize)]
+struct TomlGlossary {
+    #[serde(default)]
+    keep: Vec<String>,
+    #[serde(default)]
+    terms: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Glossary {
+    terms: Vec<Term>,
+}
+
+impl Glossary {
+    /// Read a TOML glossary, or a TSV one with `source<TAB>target` lines.
+    /// In TSV files, a line with no target marks a term not to be translated.
+    pub fn read(fname: &Path) -> Result<Self> {
+        let text = fs::read_to_string(fname)?;
+        let mut glossary = Self::default();
+        if fname.extension().is_some_and(|ext| ext == "toml") {
+            let toml: TomlGlossary = toml::from_str(&text)
+                .map_err(|e| anyhow!("Invalid glossary {}: {}", fname.display(), e))?;
+            for source in toml.keep {
+                glossary.add(&source, &source);
+            }
+            for (source, target) in toml.terms {
+                glossary.add(&source, &target);
+            }
+        } else {
+            for line in text.lines() {
+                if line.trim().is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                let (source, target) = line.split_once('\t').unwrap_or((line, ""));
+                let target = if target.trim().is_empty() {
+                    source
+                } else {
+                    target
+                };
+                glossary.add(source, target);
+            }
+        }
+        Ok(glossary)
+    }
+    /// Glossary for the language: `glossary.<iso>.tsv` if it exists, else
+    /// `glossary.tsv`, whose translated terms are dropped when it is shared
+    /// by several languages, keeping only the ones not to be translated
+    pub fn for_lang(fname: &Path, lang: &Lang, shared: bool) -> Result<Self> {
+        let local = with_iso(fname, lang.iso_code());
+        if local.exists() {
+            return Self::read(&local);
+        }
+        let mut glossary = Self::read(fname)?;
+        if shared {
+            let num = glossary.terms.len();
+            glossary.terms.retain(Term::keep);
+            if glossary.terms.len() < num {
+                println!(
+                    "Ignoring {} translated terms of {} for {}: use {} instead",
+                    num - glossary.terms.len(),
+                    fname.display(),
+                    lang,
+                    local.display()
+                );
+            }
+        }
+        Ok(glossary)
+    }
+    pub fn write_tsv(&self, fname: &Path) -> Result<()> {
+        let lines: Vec<String> = self
+            .terms
+            .iter()
+            .map(|t| match t.keep() {
+                true => t.source.clone(),
+                false => format!("{}\t{}", t.source, t.target),
+            })
+            .collect();
+        fs::write(fname, lines.join("\n") + "\n")?;
+        Ok(())
+    }
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+    pub fn len(&self) -> usize {
+        self.terms.len()
+    }
+    /// Add a term, unless already present
+    fn add(&mut self, source: &str, target: &str) {
+        let (source, target) = (source.trim(), target.trim());
+        if source.is_empty() || self.terms.iter().any(|t| t.source == source) {
+            return;
+        }
+        self.terms.push(Term {
+            source: source.to_string(),
+            target: target.to_string(),
+        });
+    }
+    /// Add the terms of another glossary, keeping ours in case of conflicts
+    pub fn merge(&mut self, other: Glossary) {
+        for t in other.terms {
+            self.add(&t.source, &t.target);
+        }
+    }
+    /// Terms occurring in the chunk
+    fn relevant(&self, chunk: &[Cue]) -> Vec<&Term> {
+        let text = chunk_text(chunk);
+        self.terms
+            .iter()
+            .filter(|t| contains_word(&text, &t.source))
+            .collect()
+    }
+    /// Glossary instructions for the translation of the chunk, if any term occurs in it
+    pub fn instructions(&self, chunk: &[Cue]) -> Option<String> {
+        let terms = self.relevant(chunk);
+        if terms.is_empty() {
+            return None;
+        }
+        let mut instr = "Use this glossary for the following terms:\n".to_string();
+        for t in terms {
+            match t.keep() {
+                true => instr.push_str(&format!("- {} (do not translate)\n", t.source)),
+                false => instr.push_str(&format!("- {} -> {}\n", t.source, t.target)),
+            }
+        }
+        Some(instr)
+    }
+    /// Cues where a glossary term occurs, but its translation doesn't
+    pub fn check(&self, orig: &[Cue], trans: &[Cue]) -> Vec<String> {
+        let mut issues = Vec::new();
+        for (o, t) in orig.iter().zip(trans) {
+            let trans_text = t.text.join("\n").to_lowercase();
+            for term in self.relevant(std::slice::from_ref(o)) {
+                if !trans_text.contains(&term.target.to_lowercase()) {
+                    issues.push(format!(
+                        "Block {}: \"{}\" not translated as \"{}\"",
+                        o.sequence, term.source, term.target
+                    ));
+                }
+            }
+        }
+        issues
+    }
+    /// Ask the model for the proper nouns and invented terms of the subtitles,
+    /// together with their translation
+    pub async fn extract(client: &Client<OpenAIConfig>, cues: &[Cue], lang: &Lang) -> Result<Self> {
+        let helper = Helper::new(client).await?;
+        let mut glossary = Self::default();
+        for part in cues.chunks(EXTRACT_CUES) {
+            let query = format!(
+                "List the proper nouns (characters, places, organizations) and the invented \
+                 terms occurring in the following subtitles, together with their translation \
+                 into {lang} language. Use the original term as translation when it must not \
+                 be translated. Reply only with a JSON object mapping each term to its \
+                 translation.\n\n{}",
+                chunk_text(part)
+            );
+            let resp = helper.ask(&query).await?;
+            match serde_json::from_str::<BTreeMap<String, String>>(find_json(&resp, '{', '}')) {
+                Ok(terms) => {
+                    for (source, target) in terms {
+                        glossary.add(&source, &target);
+                    }
+                }
+                Err(e) => println!("Skipping invalid glossary reply: {e}"),
+            }
+        }
+        helper.close().await?;
+        Ok(glossary)
+    }
+}
+
+fn chunk_text(chunk: &[Cue]) -> String {
+    let lines: Vec<String> = chunk.iter().map(|cue| cue.text.join(" ")).collect();
+    lines.join("\n")
+}
+
+/// Case-insensitive search of a whole word (or sequence of words)
+fn contains_word(text: &str, word: &str) -> bool {
+    let text = text.to_lowercase();
+    let word = word.to_lowercase();
+    let is_boundary = |c: Option<char>| c.is_none_or(|c| !c.is_alphanumeric());
+    text.match_indices(&word).any(|(beg, _)| {
+        let before = text[..beg].chars().next_back();
+        let after = text[beg + word.len()..].chars().next();
+        is_boundary(before) && is_boundary(after)
+    })
+}
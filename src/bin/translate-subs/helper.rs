@@ -0,0 +1,77 @@
+/**************************************************************************
+  Copyright 2024 Francesco Versaci (https://github.com/fversaci/)
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+**************************************************************************/
+
+//! Generic assistant, for the queries about the whole subtitles
+
+use anyhow::Result;
+use async_openai::types::{
+    CreateMessageRequestArgs, CreateRunRequest, CreateRunRequestArgs, MessageRole,
+};
+use async_openai::{config::OpenAIConfig, Client};
+use cesco_gpt::talks::{get_response, Talk};
+
+pub struct Helper {
+    client: Client<OpenAIConfig>,
+    thread_id: String,
+    run_request: CreateRunRequest,
+}
+
+impl Helper {
+    pub async fn new(client: &Client<OpenAIConfig>) -> Result<Self> {
+        let ts = Talk::Generic.get_conv(client).await?;
+        let run_request = CreateRunRequestArgs::default()
+            .assistant_id(&ts.asst.id)
+            .parallel_tool_calls(false)
+            .build()?;
+        Ok(Self {
+            client: client.clone(),
+            thread_id: ts.thread.id,
+            run_request,
+        })
+    }
+    pub async fn ask(&self, query: &str) -> Result<String> {
+        let message = CreateMessageRequestArgs::default()
+            .role(MessageRole::User)
+            .content(query)
+            .build()?;
+        self.client
+            .threads()
+            .messages(&self.thread_id)
+            .create(message)
+            .await?;
+        let run = self
+            .client
+            .threads()
+            .runs(&self.thread_id)
+            .create(self.run_request.clone())
+            .await?;
+        get_response(&self.client, &run.id, &self.thread_id).await
+    }
+    pub async fn close(self) -> Result<()> {
+        self.client.threads().delete(&self.thread_id).await?;
+        Ok(())
+    }
+}
+
+/// The JSON value in a reply, which may be wrapped in a code block or in
+/// some text, e.g., `find_json(resp, '{', '}')` for an object
+pub fn find_json(resp: &str, open: char, close: char) -> &str {
+    let beg = resp.find(open).unwrap_or(0);
+    let end = resp
+        .rfind(close)
+        .map_or(resp.len(), |e| e + close.len_utf8());
+    resp.get(beg..end).unwrap_or(resp)
+}
@@ -0,0 +1,507 @@
+/**************************************************************************
+  Copyright 2024 Francesco Versaci (https://github.com/fversaci/)
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+**************************************************************************/
+
+use anyhow::{anyhow, Result};
+use async_openai::{config::OpenAIConfig, Client};
+use cesco_gpt::talks::lang_practice::{Lang, LangLevel};
+use checkpoint::{source_hash, Checkpoint, ChunkRecord};
+use chunker::Chunker;
+use clap::Parser;
+use context::Context;
+use dual::Dual;
+use futures_util::future::try_join_all;
+use glossary::Glossary;
+use indicatif::MultiProgress;
+use progress::Progress;
+use reflow::Layout;
+use report::{Marks, Report, ReportArgs};
+use retry::Retry;
+use sdh::Sdh;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use subs::{Cue, SubFormat, Subtitles};
+use tm::{Memory, TmArgs};
+use tokio::sync::Semaphore;
+use translator::{Job, OnDone, Shared, TranslatorPool};
+
+mod checkpoint;
+mod chunker;
+mod context;
+mod dryrun;
+mod dual;
+mod frames;
+mod glossary;
+mod helper;
+mod progress;
+mod reflow;
+mod report;
+mod retry;
+mod sdh;
+mod segment;
+mod subs;
+mod tags;
+mod tm;
+mod translator;
+mod vocab;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Input subtitle file (SRT, VTT, ASS, SSA or SBV)
+    #[arg(required_unless_present = "export_tmx")]
+    in_subs: Option<PathBuf>,
+    /// Output subtitle file (`name.<iso>.ext` for each language, if several)
+    #[arg(required_unless_present = "export_tmx")]
+    out_subs: Option<PathBuf>,
+    /// Languages to translate to, separated by commas
+    #[arg(value_delimiter = ',', required_unless_present_any = ["sdh_only", "export_tmx"])]
+    lang: Vec<Lang>,
+    /// Max number of blocks per query
+    #[arg(long, default_value_t = 50)]
+    chunk: usize,
+    /// Max estimated tokens of the subtitles in each query
+    #[arg(long, default_value_t = 1500)]
+    max_tokens: usize,
+    /// Number of parallel translators, for each language
+    #[arg(long, default_value_t = 1)]
+    num: usize,
+    /// Max number of concurrent queries, for all the languages [default: translators times languages]
+    #[arg(long)]
+    max_parallel: Option<usize>,
+    /// Max number of chunks in flight [default: 4 times the translators]
+    #[arg(long)]
+    window: Option<usize>,
+    /// Input format [default: from the file extension]
+    #[arg(long, value_enum)]
+    from: Option<SubFormat>,
+    /// Output format [default: from the file extension]
+    #[arg(long, value_enum)]
+    to: Option<SubFormat>,
+    /// Translate via Chat Completions, with replies constrained by a JSON schema
+    #[arg(long, default_value_t = false)]
+    structured: bool,
+    /// Model used with --structured
+    #[arg(long, default_value = "gpt-4o-mini")]
+    model: String,
+    /// Write graded subtitles, simplified for learners at this CEFR level
+    #[arg(long, value_enum)]
+    level: Option<LangLevel>,
+    /// Instead of translating, write a vocabulary sheet (CSV, Markdown or Anki
+    /// TSV, from the output extension) of the terms above this level
+    #[arg(long, value_enum)]
+    vocab: Option<LangLevel>,
+    /// Also write the original text, for bilingual subtitles
+    #[arg(long, value_enum)]
+    dual: Option<Dual>,
+    /// Number of cues before and after each chunk, sent as context
+    #[arg(long, default_value_t = 3)]
+    context: usize,
+    /// Glossary of terms (TSV or TOML), to be translated consistently
+    #[arg(long)]
+    glossary: Option<PathBuf>,
+    /// Extract names and terms from the whole file and add them to the glossary
+    #[arg(long, default_value_t = false)]
+    auto_glossary: bool,
+    /// Resume an interrupted run, skipping the chunks already translated
+    #[arg(long, default_value_t = false)]
+    resume: bool,
+    /// Translate again only the failed, flagged or untranslated cues of this
+    /// previous output, patching them into the output file
+    #[arg(long, conflicts_with_all = ["dual", "resume"])]
+    retry_from: Option<PathBuf>,
+    /// Print the chunks and estimate the tokens and cost, without translating
+    #[arg(long, default_value_t = false, conflicts_with_all = ["vocab", "sdh_only"])]
+    dry_run: bool,
+    /// Annotations for the deaf and hard of hearing, e.g., [door slams]
+    #[arg(long, value_enum, default_value_t = Sdh::Translate)]
+    sdh: Sdh,
+    /// Only remove the SDH annotations, without translating
+    #[arg(long, default_value_t = false, conflicts_with = "vocab")]
+    sdh_only: bool,
+    #[command(flatten)]
+    layout: Layout,
+    #[command(flatten)]
+    report: ReportArgs,
+    #[command(flatten)]
+    tm: TmArgs,
+}
+
+/// Outcome of the translation into one language
+struct Summary {
+    lang: Lang,
+    out_subs: PathBuf,
+    cues: usize,
+    retries: usize,
+    flagged: f64,
+    report: Option<PathBuf>,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+    if let Some(fname) = &args.tm.export_tmx {
+        let db = args
+            .tm
+            .open()?
+            .ok_or(anyhow!("The translation memory is disabled."))?;
+        let num = tm::export_tmx(&db, fname, &args.tm.tmx_source)?;
+        println!("{} translation units exported to {}", num, fname.display());
+        return Ok(());
+    }
+    let (Some(in_subs), Some(out_subs)) = (&args.in_subs, &args.out_subs) else {
+        return Err(anyhow!("Input and output subtitles are required."));
+    };
+    let in_fmt = match args.from {
+        Some(fmt) => fmt,
+        None => SubFormat::from_path(in_subs)?,
+    };
+    let mut subs = Subtitles::read(in_subs, in_fmt)?;
+    if args.sdh == Sdh::Strip || args.sdh_only {
+        let num = subs.cues.len();
+        subs.cues = sdh::strip(subs.cues);
+        println!(
+            "SDH annotations removed, {} empty cues dropped",
+            num - subs.cues.len()
+        );
+    }
+    let chunker = Chunker::new(args.chunk, args.max_tokens)?;
+    if let Some(level) = &args.vocab {
+        let client = Client::new();
+        return vocab::run(
+            &client,
+            &subs.cues,
+            &chunker,
+            &args.lang[0],
+            level,
+            out_subs,
+        )
+        .await;
+    }
+    let out_fmt = match args.to {
+        Some(fmt) => fmt,
+        None => SubFormat::from_path(out_subs)?,
+    };
+    if args.sdh_only {
+        fs::write(out_subs, subs.render(out_fmt))?;
+        return Ok(());
+    }
+    if matches!(args.dual, Some(Dual::Tracks))
+        && !matches!(out_fmt, SubFormat::Ass | SubFormat::Ssa)
+    {
+        return Err(anyhow!("--dual tracks requires ASS or SSA output."));
+    }
+    let retry = match &args.retry_from {
+        Some(_) if args.lang.len() > 1 => {
+            return Err(anyhow!("--retry-from requires a single language."));
+        }
+        Some(prev) => Some(Retry::read(
+            prev,
+            &subs.cues,
+            args.sdh,
+            args.level.is_some(),
+        )?),
+        None => None,
+    };
+    // split the input once, for all the languages
+    let chunks = match &retry {
+        Some(retry) => retry.segments(&chunker, &subs.cues),
+        None => {
+            let mut start = 0;
+            let mut chunks = Vec::new();
+            for chunk in chunker.split(&subs.cues) {
+                chunks.push((start, chunk));
+                start += chunk.len();
+            }
+            chunks
+        }
+    };
+    if args.dry_run {
+        let glossaries = args
+            .lang
+            .iter()
+            .map(|lang| read_glossary(&args, lang))
+            .collect::<Result<Vec<_>>>()?;
+        let jobs: Vec<_> = chunks
+            .iter()
+            .filter(|(start, chunk)| {
+                retry
+                    .as_ref()
+                    .is_none_or(|r| r.kept(*start, chunk.len()).is_none())
+            })
+            .copied()
+            .collect();
+        let plan = dryrun::Plan {
+            langs: &args.lang,
+            level: args.level.as_ref(),
+            sdh: args.sdh,
+            glossaries: &glossaries,
+            context: args.context,
+            num: args.num,
+            structured: args.structured,
+        };
+        dryrun::run(&chunker, &subs.cues, &jobs, &plan);
+        return Ok(());
+    }
+    let db = args.tm.open_or_skip();
+    let client = Client::new();
+    let max_parallel = args.max_parallel.unwrap_or(args.num * args.lang.len());
+    let limit = Arc::new(Semaphore::new(max_parallel.max(1)));
+    let group = MultiProgress::new();
+    let runs = args.lang.iter().map(|lang| {
+        // a single language is written to the given file, several ones to `name.<iso>.ext`
+        let localize = |fname: &Path| match args.lang.len() {
+            1 => fname.to_path_buf(),
+            _ => with_iso(fname, lang.iso_code()),
+        };
+        let out_subs = localize(out_subs);
+        let report = args.report.report.as_deref().map(localize);
+        let run = Run {
+            args: &args,
+            client: client.clone(),
+            subs: &subs,
+            chunks: &chunks,
+            out_fmt,
+            group: &group,
+            limit: limit.clone(),
+            db: db.as_ref(),
+            retry: retry.as_ref(),
+        };
+        run.translate_into(lang.clone(), out_subs, report)
+    });
+    let summaries = try_join_all(runs).await?;
+    if let Some(db) = &db {
+        db.flush_async().await?;
+    }
+    // combined summary of all the languages
+    if summaries.len() > 1 {
+        println!("Summary:");
+        for s in &summaries {
+            println!(
+                "  {}: {} ({} cues, {} retries, {:.1}% flagged)",
+                s.lang,
+                s.out_subs.display(),
+                s.cues,
+                s.retries,
+                s.flagged
+            );
+        }
+    }
+    for s in &summaries {
+        if let Some(fname) = &s.report {
+            if s.flagged > args.report.max_flagged {
+                return Err(anyhow!(
+                    "{:.1}% of the {} cues flagged, see {}",
+                    s.flagged,
+                    s.lang,
+                    fname.display()
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Data shared by the translations into the different languages
+struct Run<'a> {
+    args: &'a Args,
+    client: Client<OpenAIConfig>,
+    subs: &'a Subtitles,
+    chunks: &'a [(usize, &'a [Cue])],
+    out_fmt: SubFormat,
+    group: &'a MultiProgress,
+    limit: Arc<Semaphore>,
+    db: Option<&'a sled::Db>,
+    /// Previous output to be patched, with `--retry-from`
+    retry: Option<&'a Retry>,
+}
+
+impl Run<'_> {
+    async fn translate_into(
+        self,
+        lang: Lang,
+        out_subs: PathBuf,
+        report_fname: Option<PathBuf>,
+    ) -> Result<Summary> {
+        let args = self.args;
+        let mut subs = self.subs.clone();
+        // chunks translated at a different level must not be resumed
+        let mut lang_key = match &args.level {
+            Some(level) => format!("{} {}", lang, level),
+            None => lang.to_string(),
+        };
+        if args.sdh == Sdh::Keep {
+            lang_key.push_str(" sdh-keep");
+        }
+        // the memory may hold the same translations to be fixed
+        let memory = self.db.filter(|_| self.retry.is_none()).map(|db| {
+            let graded = args.level.is_some();
+            Memory::new(db, &lang, &lang_key, graded, &subs.cues, args.tm.tm_context)
+        });
+        let ckpt = match self.retry {
+            Some(_) => None,
+            None => Some(Arc::new(Checkpoint::open(&out_subs, args.resume)?)),
+        };
+        let mut glossary = read_glossary(args, &lang)?;
+        if args.auto_glossary {
+            // the extracted glossary is saved for review, and reused when resuming
+            let auto_fname = with_ext(&out_subs, "glossary.tsv");
+            let auto = if args.resume && auto_fname.exists() {
+                Glossary::read(&auto_fname)?
+            } else {
+                println!("Extracting names and terms...");
+                let auto = Glossary::extract(&self.client, &subs.cues, &lang).await?;
+                auto.write_tsv(&auto_fname)?;
+                auto
+            };
+            println!("{} terms saved in {}", auto.len(), auto_fname.display());
+            glossary.merge(auto);
+        }
+        if !glossary.is_empty() {
+            println!("Using a glossary of {} terms", glossary.len());
+        }
+        // reuse the chunks completed in a previous run, queue the others
+        let context = Context::new(&subs.cues, args.context);
+        if let Some(retry) = self.retry {
+            context.accept(0, retry.cues());
+        }
+        let mut done = Vec::new();
+        let mut jobs = Vec::new();
+        for &(start, chunk) in self.chunks {
+            let hash = source_hash(chunk, &lang_key);
+            let prev = match (self.retry, &ckpt) {
+                (Some(retry), _) => retry.kept(start, chunk.len()),
+                (None, Some(ckpt)) => ckpt.get(start, chunk, &hash),
+                (None, None) => None,
+            };
+            match prev {
+                Some(trans) => {
+                    context.accept(start, &trans);
+                    done.push(Some(trans));
+                }
+                None => {
+                    done.push(None);
+                    jobs.push(Job {
+                        start,
+                        hash,
+                        chunk: chunk.to_vec(),
+                    });
+                }
+            }
+        }
+        let progress = Progress::new(done.len(), done.len() - jobs.len());
+        progress.attach(self.group, &lang.to_string());
+        let shared = Arc::new(Shared {
+            lang: lang.clone(),
+            level: args.level.clone(),
+            sdh: args.sdh,
+            chat_model: args.structured.then(|| args.model.clone()),
+            glossary,
+            context,
+            progress,
+            marks: Marks::default(),
+            memory,
+            limit: self.limit,
+        });
+        // start assistants and translate subs
+        let pool = TranslatorPool::new(args.num, self.client, shared.clone()).await?;
+        let on_done: OnDone = {
+            let ckpt = ckpt.clone();
+            Arc::new(move |job: &Job, trans: &[Cue]| match &ckpt {
+                Some(ckpt) => ckpt.save(&ChunkRecord::new(job.start, job.hash.clone(), trans)),
+                None => Ok(()),
+            })
+        };
+        let window = args.window.unwrap_or(4 * args.num);
+        let mut translated = pool.translate(jobs, window, on_done).await?.into_iter();
+        shared.progress.finish();
+        if let Some(memory) = &shared.memory {
+            shared.progress.log(format!(
+                "{} cues reused from the translation memory",
+                memory.hits()
+            ));
+        }
+        // Collect the translated blocks and write them to the output file
+        let mut cues = Vec::new();
+        for chunk in done {
+            match chunk {
+                Some(trans) => cues.extend(trans),
+                None => cues.extend(translated.next().unwrap_or_default()),
+            }
+        }
+        for issue in shared.glossary.check(&subs.cues, &cues) {
+            shared.progress.log(format!("Glossary: {issue}"));
+        }
+        args.layout.reflow(&mut cues);
+        let report = Report::new(
+            &subs.cues,
+            &cues,
+            &shared.marks,
+            &args.layout,
+            args.sdh,
+            args.level.is_some(),
+        );
+        let source = std::mem::replace(&mut subs.cues, cues);
+        match args.dual {
+            Some(dual) => dual::write(dual, &subs, &source, &out_subs, self.out_fmt)?,
+            None => fs::write(&out_subs, subs.render(self.out_fmt))?,
+        }
+        // the output is complete, the checkpoint is no longer needed
+        if let Some(ckpt) = ckpt.and_then(Arc::into_inner) {
+            ckpt.remove()?;
+        }
+        // e.g., `movie.eng.srt.flagged.tsv`, read by `--retry-from`
+        report.write_flagged(&with_ext(&out_subs, "flagged.tsv"))?;
+        if let Some(fname) = &report_fname {
+            fs::write(fname, report.render())?;
+        }
+        Ok(Summary {
+            lang,
+            out_subs,
+            cues: subs.cues.len(),
+            retries: shared.progress.retries(),
+            flagged: report.flagged(),
+            report: report_fname,
+        })
+    }
+}
+
+/// Glossary given for the language, if any
+fn read_glossary(args: &Args, lang: &Lang) -> Result<Glossary> {
+    match &args.glossary {
+        Some(fname) => Glossary::for_lang(fname, lang, args.lang.len() > 1),
+        None => Ok(Glossary::default()),
+    }
+}
+
+/// File name with an extra extension, e.g., `movie.srt` to `movie.srt.ckpt`
+fn with_ext(fname: &Path, ext: &str) -> PathBuf {
+    let mut out = fname.as_os_str().to_owned();
+    out.push(".");
+    out.push(ext);
+    PathBuf::from(out)
+}
+
+/// Localized file name, e.g., `movie.srt` to `movie.de.srt`
+fn with_iso(fname: &Path, iso: &str) -> PathBuf {
+    let stem = fname.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match fname.extension() {
+        Some(ext) => format!("{}.{}.{}", stem, iso, ext.to_string_lossy()),
+        None => format!("{}.{}", stem, iso),
+    };
+    fname.with_file_name(name)
+}
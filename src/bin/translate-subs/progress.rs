@@ -0,0 +1,69 @@
+/**************************************************************************
+  Copyright 2024 Francesco Versaci (https://github.com/fversaci/)
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+**************************************************************************/
+
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Progress bar of the translation, counting chunks and retries
+pub struct Progress {
+    bar: ProgressBar,
+    retries: AtomicUsize,
+}
+
+impl Progress {
+    pub fn new(total: usize, done: usize) -> Self {
+        let bar = ProgressBar::new(total as u64);
+        let style = ProgressStyle::with_template(
+            "{prefix}[{elapsed_precise}] {bar:40} {pos}/{len} chunks, {msg}, ETA {eta}",
+        )
+        .unwrap_or_else(|_| ProgressStyle::default_bar());
+        bar.set_style(style);
+        bar.set_position(done as u64);
+        bar.reset_eta();
+        let ret = Self {
+            bar,
+            retries: AtomicUsize::new(0),
+        };
+        ret.set_message();
+        ret
+    }
+    /// Show the bar in a group, one for each target language
+    pub fn attach(&self, group: &MultiProgress, name: &str) {
+        group.add(self.bar.clone());
+        self.bar.set_prefix(format!("{name} "));
+    }
+    fn set_message(&self) {
+        let retries = self.retries.load(Ordering::Relaxed);
+        self.bar.set_message(format!("{retries} retries"));
+    }
+    pub fn retry(&self) {
+        self.retries.fetch_add(1, Ordering::Relaxed);
+        self.set_message();
+    }
+    pub fn retries(&self) -> usize {
+        self.retries.load(Ordering::Relaxed)
+    }
+    pub fn inc(&self) {
+        self.bar.inc(1);
+    }
+    /// Print a message above the progress bar
+    pub fn log(&self, msg: impl AsRef<str>) {
+        self.bar.println(msg);
+    }
+    pub fn finish(&self) {
+        self.bar.finish();
+    }
+}
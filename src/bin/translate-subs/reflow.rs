@@ -0,0 +1,169 @@
+/**************************************************************************
+  Copyright 2024 Francesco Versaci (https://github.com/fversaci/)
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+**************************************************************************/
+
+//! Line breaking and timing adjustments of the translated cues
+
+use crate::segment::{self, Word};
+use crate::subs::{plain_text, Cue};
+use clap::Args;
+use std::time::Duration;
+
+/// Min gap kept between consecutive cues when extending them
+const MIN_GAP: Duration = Duration::from_millis(100);
+
+#[derive(Args, Debug)]
+#[command(next_help_heading = "Layout")]
+pub struct Layout {
+    /// Max reading speed, in characters per second
+    #[arg(long, default_value_t = 17.0)]
+    pub max_cps: f64,
+    /// Max characters per line (ideographs count as two)
+    #[arg(long, default_value_t = 42)]
+    pub max_chars: usize,
+    /// Max lines per cue
+    #[arg(long, default_value_t = 2)]
+    pub max_lines: usize,
+    /// Keep the translated lines and timings as they are
+    #[arg(long, default_value_t = false)]
+    pub no_reflow: bool,
+}
+
+impl Layout {
+    /// Re-wrap the lines exceeding the limits and give more time to the
+    /// cues which are too fast to read
+    pub fn reflow(&self, cues: &mut [Cue]) {
+        if self.no_reflow {
+            return;
+        }
+        for cue in cues.iter_mut() {
+            if !self.fits(&cue.text) && !is_dialogue(&cue.text) {
+                cue.text = self.wrap(&cue.text.join(" "));
+            }
+        }
+        for i in 0..cues.len() {
+            self.borrow_time(cues, i);
+        }
+    }
+    fn fits(&self, lines: &[String]) -> bool {
+        lines.len() <= self.max_lines && lines.iter().all(|l| width(l) <= self.max_chars)
+    }
+    /// Break the text into balanced lines, preferably after punctuation
+    fn wrap(&self, text: &str) -> Vec<String> {
+        let words = segment::words(text);
+        if words.is_empty() {
+            return Vec::new();
+        }
+        let total = width(text);
+        let min_lines = total.div_ceil(self.max_chars.max(1)).max(1);
+        let max_lines = self.max_lines.max(1).min(words.len());
+        let mut best: Option<(i64, Vec<String>)> = None;
+        for num_lines in min_lines.min(max_lines)..=max_lines {
+            let (cost, lines) = self.wrap_in(&words, num_lines, total);
+            if best.as_ref().is_none_or(|(c, _)| cost < *c) {
+                best = Some((cost, lines));
+            }
+        }
+        best.map(|(_, lines)| lines).unwrap_or_default()
+    }
+    /// Best split of the words into the given number of lines, by dynamic programming
+    fn wrap_in(&self, words: &[Word], num_lines: usize, total: usize) -> (i64, Vec<String>) {
+        let n = words.len();
+        let ideal = (total / num_lines) as i64;
+        let line_cost = |beg: usize, end: usize| -> i64 {
+            let line = segment::join(&words[beg..end]);
+            let w = width(&line) as i64;
+            let mut cost = (ideal - w).pow(2);
+            if w > self.max_chars as i64 {
+                cost += 10_000 * (w - self.max_chars as i64);
+            }
+            // break at phrase boundaries, if possible
+            if end < n && ends_phrase(&words[end - 1].text) {
+                cost -= 4 * ideal;
+            }
+            cost
+        };
+        // cost[k][j]: best cost of the first j words in k lines
+        let inf = i64::MAX / 2;
+        let mut cost = vec![vec![inf; n + 1]; num_lines + 1];
+        let mut prev = vec![vec![0; n + 1]; num_lines + 1];
+        cost[0][0] = 0;
+        for k in 1..=num_lines {
+            for j in k..=n {
+                for i in (k - 1)..j {
+                    if cost[k - 1][i] >= inf {
+                        continue;
+                    }
+                    let c = cost[k - 1][i] + line_cost(i, j);
+                    if c < cost[k][j] {
+                        cost[k][j] = c;
+                        prev[k][j] = i;
+                    }
+                }
+            }
+        }
+        let mut lines = Vec::new();
+        let mut end = n;
+        for k in (1..=num_lines).rev() {
+            let beg = prev[k][end];
+            lines.push(segment::join(&words[beg..end]));
+            end = beg;
+        }
+        lines.reverse();
+        (cost[num_lines][n], lines)
+    }
+    /// Extend a cue too fast to read into the gaps around it
+    fn borrow_time(&self, cues: &mut [Cue], i: usize) {
+        let chars: usize = cues[i].text.iter().map(|l| width(l)).sum();
+        let needed = Duration::from_secs_f64(chars as f64 / self.max_cps);
+        let curr = cues[i].end.saturating_sub(cues[i].start);
+        if curr >= needed {
+            return;
+        }
+        let mut missing = needed - curr;
+        // first extend the end, then anticipate the start
+        let max_end = match cues.get(i + 1) {
+            Some(next) => next.start.saturating_sub(MIN_GAP),
+            None => cues[i].end + missing,
+        };
+        let extra = max_end.saturating_sub(cues[i].end).min(missing);
+        cues[i].end += extra;
+        missing -= extra;
+        if missing.is_zero() || i == 0 {
+            return;
+        }
+        let min_start = cues[i - 1].end + MIN_GAP;
+        let extra = cues[i].start.saturating_sub(min_start).min(missing);
+        cues[i].start -= extra;
+    }
+}
+
+/// Displayed width of the line, without tags
+fn width(line: &str) -> usize {
+    segment::width(&plain_text(line))
+}
+
+fn ends_phrase(word: &str) -> bool {
+    let word = plain_text(word);
+    word.ends_with([',', '.', ';', ':', '!', '?', '…', '，', '。', '！', '？'])
+}
+
+/// Lines of different speakers, e.g., "- Hi!" "- Hello.", must not be merged
+fn is_dialogue(lines: &[String]) -> bool {
+    lines.len() > 1
+        && lines
+            .iter()
+            .all(|l| plain_text(l).trim_start().starts_with(['-', '–']))
+}
@@ -0,0 +1,261 @@
+/**************************************************************************
+  Copyright 2024 Francesco Versaci (https://github.com/fversaci/)
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+**************************************************************************/
+
+//! Quality report of the translated subtitles
+
+use crate::reflow::Layout;
+use crate::sdh::{self, Sdh};
+use crate::segment;
+use crate::subs::{plain_text, Cue};
+use anyhow::{anyhow, Result};
+use clap::Args;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+#[derive(Args, Debug)]
+#[command(next_help_heading = "Quality report")]
+pub struct ReportArgs {
+    /// Write a per-cue quality report to this file
+    #[arg(long)]
+    pub report: Option<std::path::PathBuf>,
+    /// Exit with an error when more than this percentage of cues is flagged
+    #[arg(long, default_value_t = 10.0)]
+    pub max_flagged: f64,
+}
+
+/// Events of the translation which are worth reporting
+#[derive(Default)]
+pub struct Marks {
+    /// Cues copied verbatim after repeated failures
+    verbatim: Mutex<BTreeSet<usize>>,
+    /// Cues sharing the text of a translated block with their neighbours
+    spread: Mutex<BTreeSet<usize>>,
+    /// Cues whose formatting tags were lost in the translation and repaired
+    tags: Mutex<BTreeSet<usize>>,
+}
+
+impl Marks {
+    pub fn verbatim(&self, idx: usize) {
+        self.verbatim.lock().unwrap().insert(idx);
+    }
+    pub fn spread(&self, idx: usize) {
+        self.spread.lock().unwrap().insert(idx);
+    }
+    pub fn tags(&self, idx: usize) {
+        self.tags.lock().unwrap().insert(idx);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Issue {
+    Verbatim,
+    Spread,
+    Tags,
+    ReadingSpeed(f64),
+    LongLine(usize),
+    TooManyLines(usize),
+    Untranslated,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Issue::Verbatim => write!(f, "copied verbatim after failures"),
+            Issue::Spread => write!(f, "text spread across frames"),
+            Issue::Tags => write!(f, "formatting tags repaired"),
+            Issue::ReadingSpeed(cps) => write!(f, "reading speed {cps:.1} cps"),
+            Issue::LongLine(len) => write!(f, "line of {len} characters"),
+            Issue::TooManyLines(num) => write!(f, "{num} lines"),
+            Issue::Untranslated => write!(f, "same text as the source"),
+        }
+    }
+}
+
+impl Issue {
+    fn name(&self) -> &'static str {
+        match self {
+            Issue::Verbatim => "Copied verbatim",
+            Issue::Spread => "Spread across frames",
+            Issue::Tags => "Tags repaired",
+            Issue::ReadingSpeed(_) => "Reading speed too high",
+            Issue::LongLine(_) => "Lines too long",
+            Issue::TooManyLines(_) => "Too many lines",
+            Issue::Untranslated => "Same as source",
+        }
+    }
+}
+
+/// Whether the translated cue has the same text as the source one, except
+/// for the cues made only of SDH annotations, which `--sdh keep` leaves as
+/// they are. Graded subtitles often keep the simple lines, so they are never flagged.
+pub fn untranslated(orig: &Cue, trans: &Cue, sdh: Sdh, graded: bool) -> bool {
+    if graded {
+        return false;
+    }
+    let text = |cue: &Cue| -> Vec<String> { cue.text.iter().map(|l| plain_text(l)).collect() };
+    let has_letters = trans
+        .text
+        .iter()
+        .any(|l| l.chars().any(char::is_alphabetic));
+    let kept_sdh = sdh == Sdh::Keep && sdh::only_annotations(&orig.text);
+    has_letters && !kept_sdh && text(orig) == text(trans)
+}
+
+pub struct Report {
+    /// Index, sequence number and start of the flagged cues, with their issues
+    rows: Vec<(usize, u32, Duration, Vec<Issue>)>,
+    num_cues: usize,
+}
+
+impl Report {
+    pub fn new(
+        orig: &[Cue],
+        trans: &[Cue],
+        marks: &Marks,
+        layout: &Layout,
+        sdh: Sdh,
+        graded: bool,
+    ) -> Self {
+        let verbatim = marks.verbatim.lock().unwrap();
+        let spread = marks.spread.lock().unwrap();
+        let tags = marks.tags.lock().unwrap();
+        let mut rows = Vec::new();
+        for (idx, (o, t)) in orig.iter().zip(trans).enumerate() {
+            let mut issues = Vec::new();
+            if verbatim.contains(&idx) {
+                issues.push(Issue::Verbatim);
+            }
+            if spread.contains(&idx) {
+                issues.push(Issue::Spread);
+            }
+            if tags.contains(&idx) {
+                issues.push(Issue::Tags);
+            }
+            let lines: Vec<String> = t.text.iter().map(|l| plain_text(l)).collect();
+            let chars: usize = lines.iter().map(|l| segment::width(l)).sum();
+            let secs = t.end.saturating_sub(t.start).as_secs_f64();
+            if secs > 0.0 && chars as f64 / secs > layout.max_cps {
+                issues.push(Issue::ReadingSpeed(chars as f64 / secs));
+            }
+            let longest = lines.iter().map(|l| segment::width(l)).max().unwrap_or(0);
+            if longest > layout.max_chars {
+                issues.push(Issue::LongLine(longest));
+            }
+            if lines.len() > layout.max_lines {
+                issues.push(Issue::TooManyLines(lines.len()));
+            }
+            if !verbatim.contains(&idx) && untranslated(o, t, sdh, graded) {
+                issues.push(Issue::Untranslated);
+            }
+            if !issues.is_empty() {
+                rows.push((idx, t.sequence, t.start, issues));
+            }
+        }
+        Self {
+            rows,
+            num_cues: orig.len(),
+        }
+    }
+    /// Percentage of cues with at least one issue
+    pub fn flagged(&self) -> f64 {
+        if self.num_cues == 0 {
+            return 0.0;
+        }
+        100.0 * self.rows.len() as f64 / self.num_cues as f64
+    }
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        for (_, seq, start, issues) in &self.rows {
+            let issues: Vec<String> = issues.iter().map(|i| i.to_string()).collect();
+            out.push_str(&format!(
+                "Block {} [{}]: {}\n",
+                seq,
+                timestamp(*start),
+                issues.join("; ")
+            ));
+        }
+        out.push_str("\nSummary\n");
+        out.push_str(&format!("Cues: {}\n", self.num_cues));
+        let kinds = [
+            Issue::Verbatim,
+            Issue::Spread,
+            Issue::Tags,
+            Issue::ReadingSpeed(0.0),
+            Issue::LongLine(0),
+            Issue::TooManyLines(0),
+            Issue::Untranslated,
+        ];
+        for kind in kinds {
+            let count = self
+                .rows
+                .iter()
+                .filter(|(_, _, _, issues)| issues.iter().any(|i| i.name() == kind.name()))
+                .count();
+            out.push_str(&format!("{}: {}\n", kind.name(), count));
+        }
+        out.push_str(&format!(
+            "Flagged cues: {} ({:.1}%)\n",
+            self.rows.len(),
+            self.flagged()
+        ));
+        out
+    }
+    /// Write the flagged cues (numbered from one) and their issues as TSV,
+    /// to be translated again with `--retry-from`, or remove the file if there are none
+    pub fn write_flagged(&self, fname: &Path) -> Result<()> {
+        if self.rows.is_empty() {
+            if fname.exists() {
+                fs::remove_file(fname)?;
+            }
+            return Ok(());
+        }
+        let mut out = String::new();
+        for (idx, _, _, issues) in &self.rows {
+            let issues: Vec<String> = issues.iter().map(|i| i.to_string()).collect();
+            out.push_str(&format!("{}\t{}\n", idx + 1, issues.join("; ")));
+        }
+        fs::write(fname, out)?;
+        Ok(())
+    }
+}
+
+/// Indices of the cues listed by `Report::write_flagged`
+pub fn read_flagged(fname: &Path) -> Result<Vec<usize>> {
+    let mut flagged = Vec::new();
+    for line in fs::read_to_string(fname)?.lines() {
+        let num = line.split('\t').next().unwrap_or_default();
+        match num.trim().parse::<usize>() {
+            Ok(num) if num > 0 => flagged.push(num - 1),
+            _ => return Err(anyhow!("Invalid line in {}: {}", fname.display(), line)),
+        }
+    }
+    Ok(flagged)
+}
+
+pub fn timestamp(t: Duration) -> String {
+    let ms = t.as_millis();
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        ms / 3_600_000,
+        ms / 60_000 % 60,
+        ms / 1000 % 60,
+        ms % 1000
+    )
+}
@@ -0,0 +1,159 @@
+/**************************************************************************
+  Copyright 2024 Francesco Versaci (https://github.com/fversaci/)
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+**************************************************************************/
+
+//! Targeted translation of the failed or flagged cues of a previous output
+
+use crate::chunker::Chunker;
+use crate::report::{read_flagged, untranslated};
+use crate::sdh::Sdh;
+use crate::subs::{Cue, SubFormat, Subtitles};
+use crate::with_ext;
+use anyhow::{anyhow, Result};
+use std::collections::BTreeSet;
+use std::path::Path;
+
+/// Cues of a previous output, with the indices of those to be translated again
+pub struct Retry {
+    cues: Vec<Cue>,
+    indices: BTreeSet<usize>,
+}
+
+impl Retry {
+    /// Read the previous output, taking the cues flagged in its last run
+    /// and the ones left identical to the source
+    pub fn read(prev: &Path, source: &[Cue], sdh: Sdh, graded: bool) -> Result<Self> {
+        let cues = Subtitles::read(prev, SubFormat::from_path(prev)?)?.cues;
+        if cues.len() > source.len() {
+            return Err(anyhow!(
+                "{} has {} cues, but the source has {}: use the same options as its first run.",
+                prev.display(),
+                cues.len(),
+                source.len()
+            ));
+        }
+        let cues = align(cues, source);
+        let mut indices = BTreeSet::new();
+        let flagged_fname = with_ext(prev, "flagged.tsv");
+        if flagged_fname.exists() {
+            indices.extend(read_flagged(&flagged_fname)?);
+        }
+        for (idx, (o, t)) in source.iter().zip(&cues).enumerate() {
+            if untranslated(o, t, sdh, graded) {
+                indices.insert(idx);
+            }
+        }
+        indices.retain(|&idx| idx < cues.len());
+        println!("{} cues to be translated again", indices.len());
+        Ok(Self { cues, indices })
+    }
+    /// Divide the source into the runs of cues to be kept, and the ones to
+    /// be translated again, which are further split as chunks
+    pub fn segments<'a>(&self, chunker: &Chunker, source: &'a [Cue]) -> Vec<(usize, &'a [Cue])> {
+        let mut segments = Vec::new();
+        let mut beg = 0;
+        while beg < source.len() {
+            let retry = self.indices.contains(&beg);
+            let end = (beg..source.len())
+                .find(|idx| self.indices.contains(idx) != retry)
+                .unwrap_or(source.len());
+            if retry {
+                let mut start = beg;
+                for chunk in chunker.split(&source[beg..end]) {
+                    segments.push((start, chunk));
+                    start += chunk.len();
+                }
+            } else {
+                segments.push((beg, &source[beg..end]));
+            }
+            beg = end;
+        }
+        segments
+    }
+    /// Previous translation of the cues `start..start+len`, if they are to be kept
+    pub fn kept(&self, start: usize, len: usize) -> Option<Vec<Cue>> {
+        let range = start..start + len;
+        if self.indices.range(range.clone()).next().is_some() {
+            return None;
+        }
+        Some(self.cues[range].to_vec())
+    }
+    /// All the cues of the previous output
+    pub fn cues(&self) -> &[Cue] {
+        &self.cues
+    }
+}
+
+/// Cues of the previous output, one for each source cue. The cues left
+/// empty by merged translations are not written, so each output cue is
+/// matched to the source one it overlaps the most, and the others are empty.
+fn align(prev: Vec<Cue>, source: &[Cue]) -> Vec<Cue> {
+    let mut out: Vec<Cue> = source
+        .iter()
+        .map(|cue| Cue {
+            text: Vec::new(),
+            ..cue.clone()
+        })
+        .collect();
+    let num_prev = prev.len();
+    let mut from = 0;
+    for (i, cue) in prev.into_iter().enumerate() {
+        // leave a source cue to each of the remaining output ones
+        let last = source.len() - (num_prev - i);
+        let overlap = |s: &Cue| cue.end.min(s.end).saturating_sub(cue.start.max(s.start));
+        let idx = (from..=last)
+            .take_while(|&j| j == from || source[j].start < cue.end)
+            .max_by_key(|&j| (overlap(&source[j]), std::cmp::Reverse(j)))
+            .unwrap_or(from);
+        out[idx] = cue;
+        from = idx + 1;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn cue(start: u64, end: u64, text: &str) -> Cue {
+        Cue {
+            sequence: 0,
+            start: Duration::from_millis(start),
+            end: Duration::from_millis(end),
+            text: vec![text.to_string()],
+            meta: Default::default(),
+        }
+    }
+
+    #[test]
+    fn dropped_cues_are_left_empty() {
+        let source = vec![
+            cue(0, 1000, "a"),
+            cue(1000, 2000, "b"),
+            cue(2000, 4000, "c"),
+            cue(5000, 6000, "d"),
+        ];
+        // the second cue was dropped, and the timing of the third one reflowed
+        let prev = vec![
+            cue(0, 1000, "A"),
+            cue(1900, 4200, "C"),
+            cue(5000, 6000, "D"),
+        ];
+        let cues = align(prev, &source);
+        let text: Vec<_> = cues.iter().map(|c| c.text.join(" ")).collect();
+        assert_eq!(text, vec!["A", "", "C", "D"]);
+    }
+}
@@ -0,0 +1,257 @@
+/**************************************************************************
+  Copyright 2024 Francesco Versaci (https://github.com/fversaci/)
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+**************************************************************************/
+
+//! Annotations of the subtitles for the deaf and hard of hearing (SDH),
+//! e.g., `[door slams]`, `♪ lyrics ♪` and speaker labels like `JOHN:`
+
+use crate::subs::{plain_text, Cue};
+use crate::tags::split_tags;
+use clap::ValueEnum;
+use std::ops::Range;
+
+/// Max length of a speaker label, e.g., `DR. SMITH:`
+const MAX_LABEL: usize = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Sdh {
+    /// Translate them, keeping their markers and a consistent style
+    Translate,
+    /// Keep them in the original language
+    Keep,
+    /// Remove them, dropping the cues which become empty
+    Strip,
+}
+
+/// Byte ranges of the SDH annotations in the line
+pub fn annotations(line: &str) -> Vec<Range<usize>> {
+    // song lyrics take the whole line
+    if plain_text(line).contains(['♪', '♫']) {
+        let whole = 0..line.len();
+        return vec![whole];
+    }
+    let mut ranges = Vec::new();
+    let label = speaker_label(line);
+    if let Some(label) = &label {
+        ranges.push(label.clone());
+    }
+    let mut pos = 0;
+    while let Some(beg) = line[pos..].find(['[', '(']).map(|b| b + pos) {
+        let close = if line[beg..].starts_with('[') {
+            ']'
+        } else {
+            ')'
+        };
+        let Some(len) = line[beg..].find(close) else {
+            break;
+        };
+        let range = beg..beg + len + 1;
+        // e.g., `{\pos(10,20)}` is a tag, not a sound description
+        if !in_tag(line, beg) && (close == ']' || is_sdh_paren(line, &range, label.as_ref())) {
+            ranges.push(range.clone());
+        }
+        pos = range.end;
+    }
+    ranges.sort_by_key(|r| r.start);
+    ranges
+}
+
+/// Whether the text in parentheses is a sound description, i.e., it is in
+/// capital letters, e.g., `(LAUGHS)`, or it takes the whole line, e.g.,
+/// `- (laughing)`, rather than an aside, e.g., `Hello (again)`
+fn is_sdh_paren(line: &str, range: &Range<usize>, label: Option<&Range<usize>>) -> bool {
+    let inner = plain_text(&line[range.start + 1..range.end - 1]);
+    let caps = inner.chars().any(char::is_uppercase) && !inner.chars().any(char::is_lowercase);
+    let beg = label.map_or(0, |l| l.end);
+    let rest = plain_text(&format!(
+        "{}{}",
+        &line[beg..range.start],
+        &line[range.end..]
+    ));
+    caps || rest.trim().trim_matches(['-', '–']).trim().is_empty()
+}
+
+/// Whether the position is inside a `<...>` or `{...}` tag
+fn in_tag(line: &str, pos: usize) -> bool {
+    let before = &line[..pos];
+    [('<', '>'), ('{', '}')]
+        .iter()
+        .any(|&(open, close)| match before.rfind(open) {
+            Some(beg) => before.rfind(close).is_none_or(|end| end < beg),
+            None => false,
+        })
+}
+
+/// Speaker label at the start of the line (after tags and dialogue dashes),
+/// with the spaces following it
+fn speaker_label(line: &str) -> Option<Range<usize>> {
+    let mut beg = 0;
+    loop {
+        let rest = &line[beg..];
+        let close = match rest.chars().next()? {
+            '<' => '>',
+            '{' => '}',
+            c if c.is_whitespace() || c == '-' || c == '–' => {
+                beg += c.len_utf8();
+                continue;
+            }
+            _ => break,
+        };
+        beg += rest.find(close)? + 1;
+    }
+    let colon = beg + line[beg..].find(':')?;
+    let label = &line[beg..colon];
+    let is_label = label.len() <= MAX_LABEL
+        && label.chars().any(char::is_uppercase)
+        && label
+            .chars()
+            .all(|c| c.is_uppercase() || c.is_ascii_digit() || " .'-#&".contains(c));
+    let end = colon + 1;
+    let spaces = line[end..].len() - line[end..].trim_start().len();
+    is_label.then_some(beg..end + spaces)
+}
+
+/// Whether the cues contain any SDH annotations
+pub fn has_annotations(cues: &[Cue]) -> bool {
+    cues.iter()
+        .flat_map(|c| &c.text)
+        .any(|l| !annotations(l).is_empty())
+}
+
+/// Whether the lines contain nothing but annotations, e.g., `[door slams]`
+pub fn only_annotations(lines: &[String]) -> bool {
+    lines.iter().all(|l| strip_line(l).is_none())
+}
+
+/// Instructions to translate the annotations consistently, if there are any
+pub fn instructions(chunk: &[Cue]) -> Option<String> {
+    if !has_annotations(chunk) {
+        return None;
+    }
+    Some(
+        "Some subtitles are for the deaf and hard of hearing: translate the sound \
+         descriptions in brackets keeping the brackets, e.g., [door slams], the song \
+         lyrics keeping the ♪ marks, and the speaker labels keeping the capital letters \
+         and the colon, e.g., JOHN:. Translate the same description always in the same way.\n"
+            .to_string(),
+    )
+}
+
+/// Cues without the SDH annotations, dropping the ones left empty
+pub fn strip(cues: Vec<Cue>) -> Vec<Cue> {
+    cues.into_iter()
+        .filter_map(|mut cue| {
+            let dialogue = cue.text.len() > 1;
+            cue.text = cue.text.iter().filter_map(|l| strip_line(l)).collect();
+            // a single speaker is left, the dialogue dash is not needed anymore
+            if dialogue && cue.text.len() == 1 {
+                let line = &mut cue.text[0];
+                if let Some(rest) = line.strip_prefix(['-', '–']) {
+                    *line = rest.trim_start().to_string();
+                }
+            }
+            (!cue.text.is_empty()).then_some(cue)
+        })
+        .collect()
+}
+
+/// The line without the annotations, if something is left
+fn strip_line(line: &str) -> Option<String> {
+    let mut out = String::new();
+    let mut pos = 0;
+    for range in annotations(line) {
+        if range.start >= pos {
+            out.push_str(&line[pos..range.start]);
+            pos = range.end;
+        }
+    }
+    out.push_str(&line[pos..]);
+    let mut out = collapse_spaces(&out);
+    for empty in ["<i></i>", "<b></b>", "<u></u>"] {
+        out = out.replace(empty, "");
+    }
+    let text = plain_text(&out);
+    let text = text.trim().trim_start_matches(['-', '–']).trim();
+    (!text.is_empty()).then_some(out)
+}
+
+/// Collapse the runs of whitespace, also across tags, and drop the leading
+/// and trailing ones, e.g., `<i>Hello </i>` becomes `<i>Hello</i>`
+fn collapse_spaces(line: &str) -> String {
+    let mut out = String::new();
+    // position of the last space written, if nothing visible follows it yet
+    let mut space = None;
+    let mut visible = false;
+    for (is_tag, piece) in split_tags(line) {
+        if is_tag {
+            out.push_str(piece);
+            continue;
+        }
+        for c in piece.chars() {
+            if !c.is_whitespace() {
+                out.push(c);
+                visible = true;
+                space = None;
+            } else if visible && space.is_none() {
+                space = Some(out.len());
+                out.push(' ');
+            }
+        }
+    }
+    if let Some(pos) = space {
+        out.remove(pos);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn asides_in_parentheses_are_kept() {
+        assert_eq!(
+            strip_line("JOHN: <i>Hello (again)</i> there.").as_deref(),
+            Some("<i>Hello (again)</i> there.")
+        );
+        assert_eq!(strip_line("(LAUGHS) Right.").as_deref(), Some("Right."));
+        assert_eq!(strip_line("- (laughing)"), None);
+        assert_eq!(strip_line("MARY: (whispers)"), None);
+    }
+
+    #[test]
+    fn cues_of_annotations_only() {
+        let lines = |l: &[&str]| l.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert!(only_annotations(&lines(&["[door slams]", "- (PANTING)"])));
+        assert!(only_annotations(&lines(&["<i>♪ Happy birthday ♪</i>"])));
+        assert!(!only_annotations(&lines(&["[door slams]", "Who's there?"])));
+    }
+
+    #[test]
+    fn spaces_next_to_tags_are_trimmed() {
+        assert_eq!(
+            strip_line("<i>[door slams] Who's there?</i>").as_deref(),
+            Some("<i>Who's there?</i>")
+        );
+        assert_eq!(
+            strip_line("<i>Hello</i> [SIGHS] <b>again</b>").as_deref(),
+            Some("<i>Hello</i> <b>again</b>")
+        );
+        assert_eq!(
+            strip_line("{\\an8}Wait [PANTING]").as_deref(),
+            Some("{\\an8}Wait")
+        );
+    }
+}
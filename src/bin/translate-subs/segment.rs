@@ -0,0 +1,82 @@
+/**************************************************************************
+  Copyright 2024 Francesco Versaci (https://github.com/fversaci/)
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+**************************************************************************/
+
+//! Script-aware segmentation of text at line-break opportunities
+
+use icu_segmenter::{LineSegmenter, WordSegmenter};
+use std::collections::HashSet;
+
+/// A piece of text which cannot be broken, e.g., a word, or a single
+/// ideograph in Chinese and Japanese
+#[derive(Debug, Clone)]
+pub struct Word {
+    pub text: String,
+    /// Whether the word is separated by a space from the previous one
+    pub space_before: bool,
+}
+
+/// Split a line at the Unicode line-break opportunities (UAX #14) which
+/// are also word boundaries, using dictionaries for the scripts with no
+/// spaces between words, e.g., Chinese, Japanese and Thai
+pub fn words(line: &str) -> Vec<Word> {
+    let word_breaks: HashSet<usize> = WordSegmenter::new_dictionary().segment_str(line).collect();
+    let breaks: Vec<usize> = LineSegmenter::new_dictionary()
+        .segment_str(line)
+        .filter(|b| word_breaks.contains(b))
+        .collect();
+    let mut words = Vec::new();
+    let mut space_before = false;
+    for w in breaks.windows(2) {
+        let seg = &line[w[0]..w[1]];
+        let text = seg.trim();
+        if !text.is_empty() {
+            words.push(Word {
+                text: text.to_string(),
+                space_before: space_before || seg.starts_with(char::is_whitespace),
+            });
+        }
+        space_before = seg.ends_with(char::is_whitespace);
+    }
+    words
+}
+
+/// Join words, restoring the spaces between them
+pub fn join(words: &[Word]) -> String {
+    let mut out = String::new();
+    for (i, w) in words.iter().enumerate() {
+        if i > 0 && w.space_before {
+            out.push(' ');
+        }
+        out.push_str(&w.text);
+    }
+    out
+}
+
+/// Displayed width, counting as two columns the wide characters, e.g., ideographs
+pub fn width(text: &str) -> usize {
+    let is_wide = |c: char| {
+        matches!(c as u32,
+            0x1100..=0x115F
+            | 0x2E80..=0xA4CF
+            | 0xAC00..=0xD7A3
+            | 0xF900..=0xFAFF
+            | 0xFE30..=0xFE4F
+            | 0xFF00..=0xFF60
+            | 0xFFE0..=0xFFE6
+            | 0x20000..=0x3FFFD)
+    };
+    text.chars().map(|c| if is_wide(c) { 2 } else { 1 }).sum()
+}
@@ -0,0 +1,424 @@
+/**************************************************************************
+  Copyright 2024 Francesco Versaci (https://github.com/fversaci/)
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+**************************************************************************/
+
+//! Format-neutral subtitle model, with readers and writers for each format
+
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+use std::time::Duration;
+use subtp::srt::LinePosition;
+use subtp::vtt::{CueSettings, VttBlock, VttHeader};
+
+mod ass;
+mod sbv;
+mod srt;
+mod vtt;
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum SubFormat {
+    /// SubRip
+    Srt,
+    /// WebVTT
+    Vtt,
+    /// Advanced SubStation Alpha
+    Ass,
+    /// SubStation Alpha
+    Ssa,
+    /// YouTube SubViewer
+    Sbv,
+}
+
+impl SubFormat {
+    pub fn from_path(fname: &Path) -> Result<Self> {
+        let ext = fname
+            .extension()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_lowercase();
+        Self::from_str(&ext, true)
+            .map_err(|_| anyhow!("Unknown subtitle format: {}", fname.display()))
+    }
+    /// ASS and SSA cues use override tags, the other formats HTML-like tags
+    fn uses_ass_tags(&self) -> bool {
+        matches!(self, SubFormat::Ass | SubFormat::Ssa)
+    }
+}
+
+/// Format-specific cue data, preserved when writing the same format
+#[derive(Debug, Clone, Default)]
+pub enum CueMeta {
+    #[default]
+    None,
+    Srt {
+        line_position: Option<LinePosition>,
+    },
+    Vtt {
+        identifier: Option<String>,
+        settings: Option<CueSettings>,
+    },
+    /// Fields of the event line (e.g., layer, style, margins), except times and text
+    Ass {
+        fields: Vec<(String, String)>,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct Cue {
+    pub sequence: u32,
+    pub start: Duration,
+    pub end: Duration,
+    pub text: Vec<String>,
+    pub meta: CueMeta,
+}
+
+/// Format-specific data found outside the cues
+#[derive(Debug, Clone, Default)]
+pub enum Header {
+    #[default]
+    None,
+    /// Header and non-cue blocks (STYLE, NOTE, REGION), each one before the cue of given index
+    Vtt {
+        header: VttHeader,
+        blocks: Vec<(usize, VttBlock)>,
+    },
+    /// Script sections before the events, the event format, and what follows the events
+    Ass {
+        script: String,
+        format: Vec<String>,
+        /// Non-dialogue events (e.g., comments), each one before the cue of given index
+        extra_events: Vec<(usize, String)>,
+        trailer: String,
+    },
+}
+
+impl Header {
+    /// Move the non-cue blocks, when only the given cues are kept
+    fn keep_cues(&mut self, keep: &[bool]) {
+        let new_pos = |pos: usize| keep[..pos.min(keep.len())].iter().filter(|&&k| k).count();
+        match self {
+            Header::None => (),
+            Header::Vtt { blocks, .. } => blocks.iter_mut().for_each(|(p, _)| *p = new_pos(*p)),
+            Header::Ass { extra_events, .. } => {
+                extra_events.iter_mut().for_each(|(p, _)| *p = new_pos(*p))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Subtitles {
+    pub format: SubFormat,
+    pub header: Header,
+    pub cues: Vec<Cue>,
+}
+
+impl Subtitles {
+    pub fn parse(text: &str, format: SubFormat) -> Result<Self> {
+        // skip byte order mark, if present
+        let text = text.trim_start_matches('\u{feff}');
+        match format {
+            SubFormat::Srt => srt::parse(text),
+            SubFormat::Vtt => vtt::parse(text),
+            SubFormat::Ass | SubFormat::Ssa => ass::parse(text, format),
+            SubFormat::Sbv => sbv::parse(text),
+        }
+    }
+    pub fn read(fname: &Path, format: SubFormat) -> Result<Self> {
+        let subs_f = File::open(fname)?;
+        let mut subs = String::new();
+        BufReader::new(subs_f).read_to_string(&mut subs)?;
+        Self::parse(&subs, format)
+    }
+    /// Two-track ASS/SSA subtitles, with the source cues on top of the translated ones
+    pub fn with_source_track(&self, source: &[Cue], format: SubFormat) -> Result<Self> {
+        if !format.uses_ass_tags() {
+            return Err(anyhow!("Two-track output requires ASS or SSA format."));
+        }
+        Ok(ass::with_source_track(self, source, format))
+    }
+    /// Render the subtitles in the given format, converting the cue tags if needed
+    pub fn render(&self, format: SubFormat) -> String {
+        let mut subs = self.clone();
+        // e.g., the cues left empty by a short translation merging several ones
+        let keep: Vec<bool> = subs.cues.iter().map(|c| !c.text.is_empty()).collect();
+        subs.header.keep_cues(&keep);
+        subs.cues.retain(|cue| !cue.text.is_empty());
+        if self.format.uses_ass_tags() != format.uses_ass_tags() {
+            let convert = if format.uses_ass_tags() {
+                html_to_ass
+            } else {
+                ass_to_html
+            };
+            for cue in subs.cues.iter_mut() {
+                cue.text = cue.text.iter().map(|line| convert(line)).collect();
+            }
+        }
+        if format == SubFormat::Vtt {
+            // WebVTT has no override tags, e.g., SRT positioning like {\an8}
+            for cue in subs.cues.iter_mut() {
+                cue.text = cue.text.iter().map(|line| strip_ass_tags(line)).collect();
+            }
+        }
+        match format {
+            SubFormat::Srt => srt::render(&subs),
+            SubFormat::Vtt => vtt::render(&subs),
+            SubFormat::Ass | SubFormat::Ssa => ass::render(&subs, format),
+            SubFormat::Sbv => sbv::render(&subs),
+        }
+    }
+}
+
+/// Text as displayed, without formatting tags
+pub fn plain_text(line: &str) -> String {
+    let mut out = String::new();
+    let mut skip = None;
+    for c in line.chars() {
+        match (skip, c) {
+            (None, '<') => skip = Some('>'),
+            (None, '{') => skip = Some('}'),
+            (Some(end), c) if c == end => skip = None,
+            (None, c) => out.push(c),
+            _ => (),
+        }
+    }
+    out
+}
+
+/// Remove all the `{...}` override blocks
+fn strip_ass_tags(line: &str) -> String {
+    let mut out = String::new();
+    let mut rest = line;
+    while let Some(beg) = rest.find('{') {
+        let Some(len) = rest[beg..].find('}') else {
+            break;
+        };
+        out.push_str(&rest[..beg]);
+        rest = &rest[beg + len + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Convert ASS override tags into HTML-like tags, dropping the unsupported ones
+fn ass_to_html(line: &str) -> String {
+    let mut out = String::new();
+    let mut rest = line;
+    while let Some(beg) = rest.find('{') {
+        out.push_str(&rest[..beg]);
+        let Some(len) = rest[beg..].find('}') else {
+            rest = &rest[beg..];
+            break;
+        };
+        let block = &rest[beg + 1..beg + len];
+        for tag in block.split('\\').filter(|t| !t.is_empty()) {
+            match tag {
+                "i1" => out.push_str("<i>"),
+                "i0" => out.push_str("</i>"),
+                "b1" => out.push_str("<b>"),
+                "b0" => out.push_str("</b>"),
+                "u1" => out.push_str("<u>"),
+                "u0" => out.push_str("</u>"),
+                // positioning is understood by most SRT players
+                t if t.starts_with("an") => out.push_str(&format!("{{\\{t}}}")),
+                _ => (),
+            }
+        }
+        rest = &rest[beg + len + 1..];
+    }
+    out.push_str(rest);
+    out.replace("\\h", "\u{a0}").replace("\\n", " ")
+}
+
+/// Convert HTML-like tags into ASS override tags, dropping the unsupported ones
+fn html_to_ass(line: &str) -> String {
+    let mut out = String::new();
+    let mut rest = line;
+    while let Some(beg) = rest.find('<') {
+        out.push_str(&rest[..beg]);
+        let Some(len) = rest[beg..].find('>') else {
+            rest = &rest[beg..];
+            break;
+        };
+        let tag = rest[beg + 1..beg + len].trim().to_lowercase();
+        match tag.as_str() {
+            "i" => out.push_str("{\\i1}"),
+            "/i" => out.push_str("{\\i0}"),
+            "b" => out.push_str("{\\b1}"),
+            "/b" => out.push_str("{\\b0}"),
+            "u" => out.push_str("{\\u1}"),
+            "/u" => out.push_str("{\\u0}"),
+            "/font" => out.push_str("{\\c}"),
+            t if t.starts_with("font") => {
+                // ASS colors are written as &HBBGGRR&
+                let color = t
+                    .split_once("color=")
+                    .map(|(_, c)| c.trim_matches(|c| c == '"' || c == '\'' || c == '#'));
+                match color {
+                    Some(c) if c.len() == 6 && c.is_ascii() => {
+                        let (r, g, b) = (&c[0..2], &c[2..4], &c[4..6]);
+                        let bgr = format!("{b}{g}{r}").to_uppercase();
+                        out.push_str(&format!("{{\\c&H{bgr}&}}"));
+                    }
+                    _ => (),
+                }
+            }
+            _ => (),
+        }
+        rest = &rest[beg + len + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SRT: &str = "1\n00:00:01,000 --> 00:00:02,500\n<i>Hello</i> there\nsecond line\n\n\
+                       2\n00:01:02,340 --> 00:01:04,000\n{\\an8}Top\n\n";
+
+    const VTT: &str = "WEBVTT\n\nNOTE first\n\n00:00:01.000 --> 00:00:02.500\nHello\n\n\
+                       NOTE second\n\n00:00:03.000 --> 00:00:04.000\nWorld\n";
+
+    const ASS: &str = "[Script Info]\nScriptType: v4.00+\n\n[Events]\n\
+                       Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n\
+                       Dialogue: 0,0:00:01.00,0:00:02.50,Default,,0,0,0,,{\\i1}Hello{\\i0}\\Nthere\n\
+                       Comment: 0,0:00:02.00,0:00:03.00,Default,,0,0,0,,a note\n\
+                       Dialogue: 0,0:01:02.34,0:01:04.00,Top,,0,0,0,,Again, with commas\n";
+
+    const SBV: &str = "0:00:01.000,0:00:02.500\nHello\nthere\n\n0:01:02.340,0:01:04.000\nAgain\n";
+
+    /// Timing and text of each cue
+    fn cues(subs: &Subtitles) -> Vec<(u128, u128, Vec<String>)> {
+        subs.cues
+            .iter()
+            .map(|c| (c.start.as_millis(), c.end.as_millis(), c.text.clone()))
+            .collect()
+    }
+
+    fn round_trip(text: &str, format: SubFormat) -> (Subtitles, Subtitles) {
+        let subs = Subtitles::parse(text, format).unwrap();
+        let again = Subtitles::parse(&subs.render(format), format).unwrap();
+        (subs, again)
+    }
+
+    #[test]
+    fn srt_round_trip() {
+        let (subs, again) = round_trip(SRT, SubFormat::Srt);
+        assert_eq!(
+            cues(&subs),
+            vec![
+                (
+                    1000,
+                    2500,
+                    vec!["<i>Hello</i> there".into(), "second line".into()]
+                ),
+                (62340, 64000, vec!["{\\an8}Top".into()]),
+            ]
+        );
+        assert_eq!(cues(&again), cues(&subs));
+    }
+
+    #[test]
+    fn vtt_round_trip_keeps_blocks_in_place() {
+        let (subs, again) = round_trip(VTT, SubFormat::Vtt);
+        assert_eq!(cues(&again), cues(&subs));
+        let out = subs.render(SubFormat::Vtt);
+        let pos = |s: &str| out.find(s).unwrap();
+        assert!(pos("NOTE first") < pos("Hello"));
+        assert!(pos("Hello") < pos("NOTE second"));
+        assert!(pos("NOTE second") < pos("World"));
+    }
+
+    #[test]
+    fn ass_round_trip_keeps_events_in_place() {
+        let (subs, again) = round_trip(ASS, SubFormat::Ass);
+        assert_eq!(
+            cues(&subs),
+            vec![
+                (1000, 2500, vec!["{\\i1}Hello{\\i0}".into(), "there".into()]),
+                (62340, 64000, vec!["Again, with commas".into()]),
+            ]
+        );
+        assert_eq!(cues(&again), cues(&subs));
+        let out = subs.render(SubFormat::Ass);
+        let pos = |s: &str| out.find(s).unwrap();
+        assert!(pos("Hello") < pos("Comment:"));
+        assert!(pos("Comment:") < pos("Again"));
+        assert!(out.contains("Dialogue: 0,0:01:02.34,0:01:04.00,Top,,0,0,0,,Again, with commas"));
+    }
+
+    #[test]
+    fn sbv_round_trip() {
+        let (subs, again) = round_trip(SBV, SubFormat::Sbv);
+        assert_eq!(
+            cues(&subs),
+            vec![
+                (1000, 2500, vec!["Hello".into(), "there".into()]),
+                (62340, 64000, vec!["Again".into()]),
+            ]
+        );
+        assert_eq!(cues(&again), cues(&subs));
+    }
+
+    #[test]
+    fn empty_cues_are_dropped_keeping_blocks_in_place() {
+        let mut subs = Subtitles::parse(VTT, SubFormat::Vtt).unwrap();
+        subs.cues[0].text.clear();
+        let out = subs.render(SubFormat::Vtt);
+        assert!(!out.contains("Hello"));
+        let pos = |s: &str| out.find(s).unwrap();
+        assert!(pos("NOTE first") < pos("NOTE second"));
+        assert!(pos("NOTE second") < pos("World"));
+    }
+
+    #[test]
+    fn byte_order_mark_is_skipped() {
+        let subs = Subtitles::parse(&format!("\u{feff}{SRT}"), SubFormat::Srt).unwrap();
+        assert_eq!(subs.cues.len(), 2);
+    }
+
+    #[test]
+    fn tags_are_converted_across_formats() {
+        let srt = Subtitles::parse(SRT, SubFormat::Srt).unwrap();
+        let ass = Subtitles::parse(&srt.render(SubFormat::Ass), SubFormat::Ass).unwrap();
+        assert_eq!(ass.cues[0].text[0], "{\\i1}Hello{\\i0} there");
+        assert_eq!(ass.cues[1].text[0], "{\\an8}Top");
+        let vtt = Subtitles::parse(&srt.render(SubFormat::Vtt), SubFormat::Vtt).unwrap();
+        assert_eq!(vtt.cues[1].text[0], "Top");
+        let ass = Subtitles::parse(ASS, SubFormat::Ass).unwrap();
+        let srt = Subtitles::parse(&ass.render(SubFormat::Srt), SubFormat::Srt).unwrap();
+        assert_eq!(srt.cues[0].text, vec!["<i>Hello</i>", "there"]);
+    }
+
+    #[test]
+    fn timings_survive_format_changes() {
+        for format in [
+            SubFormat::Vtt,
+            SubFormat::Ass,
+            SubFormat::Ssa,
+            SubFormat::Sbv,
+        ] {
+            let srt = Subtitles::parse(SRT, SubFormat::Srt).unwrap();
+            let out = Subtitles::parse(&srt.render(format), format).unwrap();
+            let times: Vec<_> = cues(&out).into_iter().map(|(s, e, _)| (s, e)).collect();
+            assert_eq!(times, vec![(1000, 2500), (62340, 64000)], "{format:?}");
+        }
+    }
+}
@@ -0,0 +1,309 @@
+/**************************************************************************
+  Copyright 2024 Francesco Versaci (https://github.com/fversaci/)
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+**************************************************************************/
+
+use crate::subs::{Cue, CueMeta, Header, SubFormat, Subtitles};
+use anyhow::{anyhow, Result};
+use std::time::Duration;
+
+const ASS_SCRIPT: &str = "[Script Info]
+ScriptType: v4.00+
+PlayResX: 384
+PlayResY: 288
+WrapStyle: 0
+ScaledBorderAndShadow: yes
+
+[V4+ Styles]
+Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, \
+Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, \
+Shadow, Alignment, MarginL, MarginR, MarginV, Encoding
+Style: Default,Arial,20,&H00FFFFFF,&H000000FF,&H00000000,&H80000000,0,0,0,0,100,100,0,0,1,2,1,\
+2,10,10,10,1
+";
+
+const SSA_SCRIPT: &str = "[Script Info]
+ScriptType: v4.00
+
+[V4 Styles]
+Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, TertiaryColour, BackColour, \
+Bold, Italic, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, AlphaLevel, \
+Encoding
+Style: Default,Arial,20,16777215,65535,65535,0,0,0,1,2,1,2,10,10,10,0,1
+";
+
+const ASS_FORMAT: &str = "Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text";
+const SSA_FORMAT: &str = "Marked, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text";
+
+fn split_format(format: &str) -> Vec<String> {
+    format.split(',').map(|f| f.trim().to_string()).collect()
+}
+
+/// Parse a timestamp such as `0:01:02.34` (centiseconds)
+fn parse_time(txt: &str) -> Result<Duration> {
+    let bad = || anyhow!("Invalid ASS timestamp: {txt}");
+    let mut parts = txt.trim().splitn(3, ':');
+    let hours: u64 = parts.next().ok_or_else(bad)?.parse()?;
+    let mins: u64 = parts.next().ok_or_else(bad)?.parse()?;
+    let secs: f64 = parts.next().ok_or_else(bad)?.parse()?;
+    let millis = ((hours * 60 + mins) * 60) * 1000 + (secs * 1000.0).round() as u64;
+    Ok(Duration::from_millis(millis))
+}
+
+fn format_time(time: Duration) -> String {
+    let centis = time.as_millis() / 10;
+    let (secs, centis) = (centis / 100, centis % 100);
+    let (mins, secs) = (secs / 60, secs % 60);
+    let (hours, mins) = (mins / 60, mins % 60);
+    format!("{hours}:{mins:02}:{secs:02}.{centis:02}")
+}
+
+fn is_section(line: &str) -> bool {
+    line.starts_with('[') && line.ends_with(']')
+}
+
+pub fn parse(text: &str, format: SubFormat) -> Result<Subtitles> {
+    let mut lines = text.lines().map(|l| l.trim_end_matches('\r'));
+    // everything before the events is kept verbatim
+    let mut script = String::new();
+    for line in lines.by_ref() {
+        if line.trim().eq_ignore_ascii_case("[Events]") {
+            break;
+        }
+        script.push_str(line);
+        script.push('\n');
+    }
+    let mut ev_format = split_format(ASS_FORMAT);
+    let mut extra_events = Vec::new();
+    let mut trailer = String::new();
+    let mut cues = Vec::new();
+    for line in lines.by_ref() {
+        if is_section(line.trim()) {
+            trailer.push_str(line);
+            trailer.push('\n');
+            break;
+        }
+        if let Some(fmt) = line.strip_prefix("Format:") {
+            ev_format = split_format(fmt);
+        } else if let Some(event) = line.strip_prefix("Dialogue:") {
+            let values = event.trim_start().splitn(ev_format.len(), ',');
+            let mut cue = Cue {
+                sequence: cues.len() as u32 + 1,
+                start: Duration::ZERO,
+                end: Duration::ZERO,
+                text: Vec::new(),
+                meta: CueMeta::None,
+            };
+            let mut fields = Vec::new();
+            for (name, value) in ev_format.iter().zip(values) {
+                match name.to_lowercase().as_str() {
+                    "start" => cue.start = parse_time(value)?,
+                    "end" => cue.end = parse_time(value)?,
+                    "text" => cue.text = value.split("\\N").map(|l| l.to_string()).collect(),
+                    _ => fields.push((name.clone(), value.to_string())),
+                }
+            }
+            cue.meta = CueMeta::Ass { fields };
+            cues.push(cue);
+        } else if !line.trim().is_empty() {
+            // comments and other events are kept untranslated, in place
+            extra_events.push((cues.len(), line.to_string()));
+        }
+    }
+    for line in lines {
+        trailer.push_str(line);
+        trailer.push('\n');
+    }
+    Ok(Subtitles {
+        format,
+        header: Header::Ass {
+            script,
+            format: ev_format,
+            extra_events,
+            trailer,
+        },
+        cues,
+    })
+}
+
+fn default_field(name: &str) -> &'static str {
+    match name.to_lowercase().as_str() {
+        "marked" => "Marked=0",
+        "style" => "Default",
+        "layer" | "marginl" | "marginr" | "marginv" => "0",
+        _ => "",
+    }
+}
+
+pub fn render(subs: &Subtitles, format: SubFormat) -> String {
+    let (script, ev_format, extra_events, trailer) = match &subs.header {
+        Header::Ass {
+            script,
+            format,
+            extra_events,
+            trailer,
+        } => (
+            script.clone(),
+            format.clone(),
+            extra_events.clone(),
+            trailer.clone(),
+        ),
+        _ => {
+            let (script, ev_format) = match format {
+                SubFormat::Ssa => (SSA_SCRIPT, SSA_FORMAT),
+                _ => (ASS_SCRIPT, ASS_FORMAT),
+            };
+            let script = format!("{script}\n");
+            (script, split_format(ev_format), Vec::new(), String::new())
+        }
+    };
+    let mut out = script;
+    out.push_str("[Events]\n");
+    out.push_str(&format!("Format: {}\n", ev_format.join(", ")));
+    let mut extra = extra_events.into_iter().peekable();
+    for (i, cue) in subs.cues.iter().enumerate() {
+        while let Some((_, line)) = extra.next_if(|(pos, _)| *pos <= i) {
+            out.push_str(&line);
+            out.push('\n');
+        }
+        let fields = match &cue.meta {
+            CueMeta::Ass { fields } => fields.as_slice(),
+            _ => &[],
+        };
+        let values: Vec<String> = ev_format
+            .iter()
+            .map(|name| match name.to_lowercase().as_str() {
+                "start" => format_time(cue.start),
+                "end" => format_time(cue.end),
+                "text" => cue.text.join("\\N"),
+                _ => fields
+                    .iter()
+                    .find(|(n, _)| n.eq_ignore_ascii_case(name))
+                    .map_or(default_field(name).to_string(), |(_, v)| v.clone()),
+            })
+            .collect();
+        out.push_str(&format!("Dialogue: {}\n", values.join(",")));
+    }
+    for (_, line) in extra {
+        out.push_str(&line);
+        out.push('\n');
+    }
+    if !trailer.is_empty() {
+        out.push('\n');
+        out.push_str(&trailer);
+    }
+    out
+}
+
+/// Copy the default style (or the first one) of the script into a new one,
+/// with the given name, aligned at the top center
+fn add_top_style(script: &str, name: &str, format: SubFormat) -> String {
+    let mut fields: Vec<String> = Vec::new();
+    let mut style: Option<&str> = None;
+    let mut in_styles = false;
+    let mut insert_at = None;
+    let lines: Vec<&str> = script.lines().collect();
+    for (i, line) in lines.iter().enumerate() {
+        let trimmed = line.trim();
+        if is_section(trimmed) {
+            in_styles = trimmed.to_lowercase().contains("styles");
+            continue;
+        }
+        if !in_styles {
+            continue;
+        }
+        if let Some(fmt) = trimmed.strip_prefix("Format:") {
+            fields = split_format(fmt);
+        } else if let Some(st) = trimmed.strip_prefix("Style:") {
+            insert_at = Some(i + 1);
+            let is_default = st.trim_start().starts_with("Default,");
+            if style.is_none() || is_default {
+                style = Some(st.trim_start());
+            }
+        }
+    }
+    let (Some(style), Some(insert_at)) = (style, insert_at) else {
+        return script.to_string();
+    };
+    // numpad alignment in ASS, legacy alignment in SSA
+    let top = match format {
+        SubFormat::Ssa => "6",
+        _ => "8",
+    };
+    let values: Vec<String> = fields
+        .iter()
+        .zip(style.split(','))
+        .map(|(field, value)| match field.to_lowercase().as_str() {
+            "name" => name.to_string(),
+            "alignment" => top.to_string(),
+            _ => value.to_string(),
+        })
+        .collect();
+    let mut out: Vec<String> = lines.iter().map(|l| l.to_string()).collect();
+    out.insert(insert_at, format!("Style: {}", values.join(",")));
+    out.join("\n") + "\n"
+}
+
+/// Subtitles with the source cues as a second track, at the top of the screen
+pub fn with_source_track(trans: &Subtitles, source: &[Cue], format: SubFormat) -> Subtitles {
+    let style = "Source";
+    let header = match &trans.header {
+        Header::Ass {
+            script,
+            format: ev_format,
+            extra_events,
+            trailer,
+        } => Header::Ass {
+            script: add_top_style(script, style, format),
+            format: ev_format.clone(),
+            // each cue is preceded by its source one
+            extra_events: extra_events
+                .iter()
+                .map(|(pos, line)| (2 * pos, line.clone()))
+                .collect(),
+            trailer: trailer.clone(),
+        },
+        _ => {
+            let (script, ev_format) = match format {
+                SubFormat::Ssa => (SSA_SCRIPT, SSA_FORMAT),
+                _ => (ASS_SCRIPT, ASS_FORMAT),
+            };
+            Header::Ass {
+                script: add_top_style(script, style, format) + "\n",
+                format: split_format(ev_format),
+                extra_events: Vec::new(),
+                trailer: String::new(),
+            }
+        }
+    };
+    let mut cues = Vec::new();
+    for (t, s) in trans.cues.iter().zip(source) {
+        let mut fields = match &s.meta {
+            CueMeta::Ass { fields } => fields.clone(),
+            _ => Vec::new(),
+        };
+        fields.retain(|(name, _)| !name.eq_ignore_ascii_case("style"));
+        fields.push(("Style".to_string(), style.to_string()));
+        cues.push(Cue {
+            meta: CueMeta::Ass { fields },
+            ..s.clone()
+        });
+        cues.push(t.clone());
+    }
+    Subtitles {
+        format: trans.format,
+        header,
+        cues,
+    }
+}
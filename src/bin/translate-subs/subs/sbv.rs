@@ -0,0 +1,75 @@
+/**************************************************************************
+  Copyright 2024 Francesco Versaci (https://github.com/fversaci/)
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+**************************************************************************/
+
+use crate::subs::{Cue, CueMeta, Header, SubFormat, Subtitles};
+use anyhow::{anyhow, Result};
+use std::time::Duration;
+
+/// Parse a timestamp such as `0:01:02.345`
+fn parse_time(txt: &str) -> Result<Duration> {
+    let bad = || anyhow!("Invalid SBV timestamp: {txt}");
+    let mut parts = txt.trim().rsplitn(3, ':');
+    let secs: f64 = parts.next().ok_or_else(bad)?.parse()?;
+    let mins: u64 = parts.next().ok_or_else(bad)?.parse()?;
+    let hours: u64 = parts.next().map_or(Ok(0), |h| h.parse())?;
+    let millis = ((hours * 60 + mins) * 60) * 1000 + (secs * 1000.0).round() as u64;
+    Ok(Duration::from_millis(millis))
+}
+
+fn format_time(time: Duration) -> String {
+    let millis = time.as_millis();
+    let (secs, millis) = (millis / 1000, millis % 1000);
+    let (mins, secs) = (secs / 60, secs % 60);
+    let (hours, mins) = (mins / 60, mins % 60);
+    format!("{hours}:{mins:02}:{secs:02}.{millis:03}")
+}
+
+pub fn parse(text: &str) -> Result<Subtitles> {
+    let mut cues = Vec::new();
+    let text = text.replace("\r\n", "\n");
+    for block in text.split("\n\n").filter(|b| !b.trim().is_empty()) {
+        let mut lines = block.trim_matches('\n').lines();
+        let times = lines.next().unwrap_or_default();
+        let (start, end) = times
+            .split_once(',')
+            .ok_or(anyhow!("Invalid SBV timing line: {times}"))?;
+        cues.push(Cue {
+            sequence: cues.len() as u32 + 1,
+            start: parse_time(start)?,
+            end: parse_time(end)?,
+            text: lines.map(|l| l.to_string()).collect(),
+            meta: CueMeta::None,
+        });
+    }
+    Ok(Subtitles {
+        format: SubFormat::Sbv,
+        header: Header::None,
+        cues,
+    })
+}
+
+pub fn render(subs: &Subtitles) -> String {
+    let mut out = String::new();
+    for cue in &subs.cues {
+        out.push_str(&format!(
+            "{},{}\n{}\n\n",
+            format_time(cue.start),
+            format_time(cue.end),
+            cue.text.join("\n")
+        ));
+    }
+    out
+}
@@ -0,0 +1,60 @@
+/**************************************************************************
+  Copyright 2024 Francesco Versaci (https://github.com/fversaci/)
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+**************************************************************************/
+
+use crate::subs::{Cue, CueMeta, Header, SubFormat, Subtitles};
+use anyhow::Result;
+use subtp::srt::{SrtSubtitle, SubRip};
+
+pub fn parse(text: &str) -> Result<Subtitles> {
+    let srt = SubRip::parse(text)?;
+    let cues = srt
+        .subtitles
+        .into_iter()
+        .map(|sub| Cue {
+            sequence: sub.sequence,
+            start: sub.start.into(),
+            end: sub.end.into(),
+            text: sub.text,
+            meta: CueMeta::Srt {
+                line_position: sub.line_position,
+            },
+        })
+        .collect();
+    Ok(Subtitles {
+        format: SubFormat::Srt,
+        header: Header::None,
+        cues,
+    })
+}
+
+pub fn render(subs: &Subtitles) -> String {
+    let mut out = String::new();
+    for (i, cue) in subs.cues.iter().enumerate() {
+        let line_position = match &cue.meta {
+            CueMeta::Srt { line_position } => *line_position,
+            _ => None,
+        };
+        let sub = SrtSubtitle {
+            sequence: i as u32 + 1,
+            start: cue.start.into(),
+            end: cue.end.into(),
+            text: cue.text.clone(),
+            line_position,
+        };
+        out.push_str(&format!("{}\n", sub));
+    }
+    out
+}
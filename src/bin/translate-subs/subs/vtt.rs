@@ -0,0 +1,86 @@
+/**************************************************************************
+  Copyright 2024 Francesco Versaci (https://github.com/fversaci/)
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+**************************************************************************/
+
+use crate::subs::{Cue, CueMeta, Header, SubFormat, Subtitles};
+use anyhow::Result;
+use subtp::vtt::{VttBlock, VttCue, VttHeader, VttTimings, WebVtt};
+
+pub fn parse(text: &str) -> Result<Subtitles> {
+    let vtt = WebVtt::parse(text)?;
+    let mut cues = Vec::new();
+    let mut blocks = Vec::new();
+    for block in vtt.blocks {
+        match block {
+            VttBlock::Que(cue) => cues.push(Cue {
+                sequence: cues.len() as u32 + 1,
+                start: cue.timings.start.into(),
+                end: cue.timings.end.into(),
+                text: cue.payload,
+                meta: CueMeta::Vtt {
+                    identifier: cue.identifier,
+                    settings: cue.settings,
+                },
+            }),
+            // keep STYLE, NOTE and REGION blocks in place
+            VttBlock::Style(mut style) => {
+                style.style.truncate(style.style.trim_end().len());
+                blocks.push((cues.len(), style.into()));
+            }
+            block => blocks.push((cues.len(), block)),
+        }
+    }
+    Ok(Subtitles {
+        format: SubFormat::Vtt,
+        header: Header::Vtt {
+            header: vtt.header,
+            blocks,
+        },
+        cues,
+    })
+}
+
+pub fn render(subs: &Subtitles) -> String {
+    let (header, extra) = match &subs.header {
+        Header::Vtt { header, blocks } => (header.clone(), blocks.clone()),
+        _ => (VttHeader::default(), Vec::new()),
+    };
+    let mut extra = extra.into_iter().peekable();
+    let mut blocks = Vec::new();
+    for (i, cue) in subs.cues.iter().enumerate() {
+        while let Some((_, block)) = extra.next_if(|(pos, _)| *pos <= i) {
+            blocks.push(block);
+        }
+        let (identifier, settings) = match &cue.meta {
+            CueMeta::Vtt {
+                identifier,
+                settings,
+            } => (identifier.clone(), settings.clone()),
+            _ => (None, None),
+        };
+        let vtt_cue = VttCue {
+            identifier,
+            timings: VttTimings {
+                start: cue.start.into(),
+                end: cue.end.into(),
+            },
+            settings,
+            payload: cue.text.clone(),
+        };
+        blocks.push(vtt_cue.into());
+    }
+    blocks.extend(extra.map(|(_, block)| block));
+    WebVtt { header, blocks }.render()
+}
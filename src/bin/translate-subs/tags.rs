@@ -0,0 +1,316 @@
+/**************************************************************************
+  Copyright 2024 Francesco Versaci (https://github.com/fversaci/)
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+**************************************************************************/
+
+//! Masking of the formatting tags (e.g., `<i>` or `{\an8}`), so that
+//! they survive the translation
+
+use crate::sdh;
+use crate::subs::Cue;
+
+/// Tags at the start and at the end of a cue, which are not sent for translation
+#[derive(Default)]
+struct Outer {
+    head: String,
+    tail: String,
+    /// Whether they enclose each line, e.g., `<i>...</i>` for off-screen speech
+    each_line: bool,
+}
+
+/// Tags of a chunk: the outer ones are removed from the text, while the
+/// ones inside the lines are replaced by placeholders, e.g., `⟦1⟧`
+pub struct Masks {
+    outer: Vec<Outer>,
+    /// Inner tags, together with the index of their cue
+    inner: Vec<(String, usize)>,
+}
+
+impl Masks {
+    /// Masks of the chunk, with the masked text of its cues. With
+    /// `keep_sdh`, the SDH annotations are masked too, to keep them untranslated.
+    pub fn new(chunk: &[Cue], keep_sdh: bool) -> (Self, Vec<Vec<String>>) {
+        let mut masks = Masks {
+            outer: Vec::new(),
+            inner: Vec::new(),
+        };
+        let mut text = Vec::new();
+        for (idx, cue) in chunk.iter().enumerate() {
+            let (outer, lines) = strip_outer(&cue.text);
+            let lines = lines
+                .iter()
+                .map(|line| {
+                    let mut out = String::new();
+                    let mut pos = 0;
+                    let kept = match keep_sdh {
+                        true => sdh::annotations(line),
+                        false => Vec::new(),
+                    };
+                    for range in kept {
+                        if range.start >= pos {
+                            out.push_str(&masks.mask_tags(&line[pos..range.start], idx));
+                            out.push_str(&masks.push(&line[range.clone()], idx));
+                            pos = range.end;
+                        }
+                    }
+                    out.push_str(&masks.mask_tags(&line[pos..], idx));
+                    out
+                })
+                .collect();
+            masks.outer.push(outer);
+            text.push(lines);
+        }
+        (masks, text)
+    }
+    /// Add a masked piece of the cue, returning its placeholder
+    fn push(&mut self, piece: &str, idx: usize) -> String {
+        self.inner.push((piece.to_string(), idx));
+        placeholder(self.inner.len())
+    }
+    fn mask_tags(&mut self, text: &str, idx: usize) -> String {
+        let mut out = String::new();
+        for (is_tag, piece) in split_tags(text) {
+            if is_tag {
+                out.push_str(&self.push(piece, idx));
+            } else {
+                out.push_str(piece);
+            }
+        }
+        out
+    }
+    /// Instructions about the placeholders, if any
+    pub fn instructions(&self) -> Option<String> {
+        if self.inner.is_empty() {
+            return None;
+        }
+        Some(
+            "The subtitles contain placeholders such as ⟦1⟧, for formatting tags or text \
+             to be kept as it is: copy each of them exactly once, next to the translation \
+             of the words they refer to.\n"
+                .to_string(),
+        )
+    }
+    /// Put the tags back into the translated cues, returning the indices of
+    /// the cues whose tags were lost or unbalanced, and had to be repaired
+    pub fn restore(&self, orig: &[Cue], trans: &mut [Cue]) -> Vec<usize> {
+        let mut used = vec![false; self.inner.len()];
+        let mut repaired = Vec::new();
+        for (idx, cue) in trans.iter_mut().enumerate() {
+            let mut ok = true;
+            for line in cue.text.iter_mut() {
+                let (restored, line_ok) = self.unmask(line, &mut used);
+                *line = restored;
+                ok &= line_ok;
+            }
+            if let Some(outer) = self.outer.get(idx) {
+                add_outer(outer, &mut cue.text);
+            }
+            // unbalanced tags in the source are left as they are
+            if orig.get(idx).is_some_and(|o| is_balanced(&o.text)) && !is_balanced(&cue.text) {
+                balance(&mut cue.text);
+                ok = false;
+            }
+            if !ok {
+                repaired.push(idx);
+            }
+        }
+        for ((_, idx), used) in self.inner.iter().zip(used) {
+            if !used && !repaired.contains(idx) {
+                repaired.push(*idx);
+            }
+        }
+        repaired.sort_unstable();
+        repaired
+    }
+    /// Replace the placeholders with their tags, dropping the unknown and repeated ones
+    fn unmask(&self, line: &str, used: &mut [bool]) -> (String, bool) {
+        let mut out = String::new();
+        let mut ok = true;
+        let mut rest = line;
+        while let Some(beg) = rest.find('⟦') {
+            out.push_str(&rest[..beg]);
+            rest = &rest[beg + '⟦'.len_utf8()..];
+            let Some(len) = rest.find('⟧') else {
+                ok = false;
+                continue;
+            };
+            let num = rest[..len].trim().parse::<usize>().ok();
+            rest = &rest[len + '⟧'.len_utf8()..];
+            match num.filter(|n| (1..=used.len()).contains(n) && !used[n - 1]) {
+                Some(n) => {
+                    used[n - 1] = true;
+                    out.push_str(&self.inner[n - 1].0);
+                }
+                None => ok = false,
+            }
+        }
+        out.push_str(rest);
+        (out, ok)
+    }
+}
+
+fn placeholder(num: usize) -> String {
+    format!("⟦{num}⟧")
+}
+
+/// Split a line into tags, i.e., `<...>` and `{...}`, and text
+pub fn split_tags(line: &str) -> Vec<(bool, &str)> {
+    let mut out = Vec::new();
+    let mut rest = line;
+    while let Some(beg) = rest.find(['<', '{']) {
+        let close = if rest[beg..].starts_with('<') {
+            '>'
+        } else {
+            '}'
+        };
+        let Some(len) = rest[beg..].find(close) else {
+            break;
+        };
+        if beg > 0 {
+            out.push((false, &rest[..beg]));
+        }
+        out.push((true, &rest[beg..=beg + len]));
+        rest = &rest[beg + len + 1..];
+    }
+    if !rest.is_empty() {
+        out.push((false, rest));
+    }
+    out
+}
+
+/// Tags at the start of the line, text, and tags at its end
+fn outer_tags(line: &str) -> (&str, &str, &str) {
+    let pieces = split_tags(line);
+    let num_head = pieces.iter().take_while(|(is_tag, _)| *is_tag).count();
+    let num_tail = pieces[num_head..]
+        .iter()
+        .rev()
+        .take_while(|(is_tag, _)| *is_tag)
+        .count();
+    let head: usize = pieces[..num_head].iter().map(|(_, p)| p.len()).sum();
+    let tail: usize = pieces[pieces.len() - num_tail..]
+        .iter()
+        .map(|(_, p)| p.len())
+        .sum();
+    let tail = line.len() - tail;
+    (&line[..head], &line[head..tail], &line[tail..])
+}
+
+/// Remove the tags at the start and at the end of the cue, or of each of
+/// its lines when they are the same for all of them
+fn strip_outer(lines: &[String]) -> (Outer, Vec<String>) {
+    let parts: Vec<_> = lines.iter().map(|l| outer_tags(l)).collect();
+    let (Some(first), Some(last)) = (parts.first(), parts.last()) else {
+        return (Outer::default(), Vec::new());
+    };
+    let each_line = parts.iter().all(|p| p.0 == first.0 && p.2 == first.2);
+    let outer = Outer {
+        head: first.0.to_string(),
+        tail: if each_line { first.2 } else { last.2 }.to_string(),
+        each_line,
+    };
+    let num = lines.len();
+    let text = parts
+        .iter()
+        .zip(lines)
+        .enumerate()
+        .map(|(i, ((head, mid, tail), line))| match each_line {
+            true => mid.to_string(),
+            false => {
+                let beg = if i == 0 { head.len() } else { 0 };
+                let end = line.len() - if i == num - 1 { tail.len() } else { 0 };
+                line[beg..end].to_string()
+            }
+        })
+        .collect();
+    (outer, text)
+}
+
+fn add_outer(outer: &Outer, lines: &mut [String]) {
+    if outer.each_line {
+        for line in lines.iter_mut() {
+            *line = format!("{}{}{}", outer.head, line, outer.tail);
+        }
+        return;
+    }
+    if let Some(first) = lines.first_mut() {
+        first.insert_str(0, &outer.head);
+    }
+    if let Some(last) = lines.last_mut() {
+        last.push_str(&outer.tail);
+    }
+}
+
+/// Name of an HTML-like tag, and whether it is a closing one, e.g., `</i>`
+fn html_tag(tag: &str) -> Option<(String, bool)> {
+    let inner = tag.strip_prefix('<')?.strip_suffix('>')?.trim();
+    let (name, closing) = match inner.strip_prefix('/') {
+        Some(name) => (name, true),
+        None => (inner, false),
+    };
+    let name: String = name
+        .chars()
+        .take_while(|c| c.is_ascii_alphabetic())
+        .collect::<String>()
+        .to_lowercase();
+    matches!(name.as_str(), "i" | "b" | "u" | "s" | "font").then_some((name, closing))
+}
+
+fn is_balanced(lines: &[String]) -> bool {
+    let mut open: Vec<String> = Vec::new();
+    for line in lines {
+        for (_, tag) in split_tags(line).into_iter().filter(|(is_tag, _)| *is_tag) {
+            match html_tag(tag) {
+                Some((name, false)) => open.push(name),
+                Some((name, true)) if open.last() == Some(&name) => {
+                    open.pop();
+                }
+                Some(_) => return false,
+                None => (),
+            }
+        }
+    }
+    open.is_empty()
+}
+
+/// Drop the unmatched closing tags, and close the tags left open at the end of the cue
+fn balance(lines: &mut [String]) {
+    let mut open: Vec<String> = Vec::new();
+    for line in lines.iter_mut() {
+        let mut out = String::new();
+        for (is_tag, piece) in split_tags(line) {
+            match is_tag.then(|| html_tag(piece)).flatten() {
+                Some((name, false)) => open.push(name),
+                Some((name, true)) => match open.iter().rposition(|n| *n == name) {
+                    // close the inner tags too, to keep them nested
+                    Some(pos) => {
+                        for n in open.drain(pos + 1..).rev() {
+                            out.push_str(&format!("</{n}>"));
+                        }
+                        open.pop();
+                    }
+                    None => continue,
+                },
+                None => (),
+            }
+            out.push_str(piece);
+        }
+        *line = out;
+    }
+    if let Some(last) = lines.last_mut() {
+        for name in open.iter().rev() {
+            last.push_str(&format!("</{name}>"));
+        }
+    }
+}
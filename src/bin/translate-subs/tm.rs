@@ -0,0 +1,211 @@
+/**************************************************************************
+  Copyright 2024 Francesco Versaci (https://github.com/fversaci/)
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+**************************************************************************/
+
+//! Translation memory of the subtitle lines, reused across runs and
+//! exportable as TMX
+
+use crate::checkpoint::{fnv1a, FNV_OFFSET};
+use crate::subs::Cue;
+use anyhow::{anyhow, Result};
+use cesco_gpt::talks::lang_practice::Lang;
+use clap::Args;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[derive(Args, Debug)]
+#[command(next_help_heading = "Translation memory")]
+pub struct TmArgs {
+    /// Directory of the translation memory [default: e.g., ~/.local/share/cesco-gpt/tm]
+    #[arg(long)]
+    pub tm: Option<PathBuf>,
+    /// Do not read nor update the translation memory
+    #[arg(long, default_value_t = false)]
+    pub no_tm: bool,
+    /// Reuse a line only if the previous and next ones match too
+    #[arg(long, default_value_t = false)]
+    pub tm_context: bool,
+    /// Export the translation memory as TMX to this file, and exit
+    #[arg(long)]
+    pub export_tmx: Option<PathBuf>,
+    /// Language code of the source lines in the TMX file
+    #[arg(long, default_value = "und")]
+    pub tmx_source: String,
+}
+
+impl TmArgs {
+    /// Open the translation memory, unless disabled
+    pub fn open(&self) -> Result<Option<sled::Db>> {
+        if self.no_tm {
+            return Ok(None);
+        }
+        let dir = match &self.tm {
+            Some(dir) => dir.clone(),
+            None => dirs::data_dir()
+                .ok_or(anyhow!("Cannot find data directory."))?
+                .join("cesco-gpt")
+                .join("tm"),
+        };
+        fs::create_dir_all(&dir)?;
+        Ok(Some(sled::open(&dir)?))
+    }
+    /// Open the translation memory for a translation, going on without it
+    /// if it cannot be opened, e.g., when another run is using it
+    pub fn open_or_skip(&self) -> Option<sled::Db> {
+        self.open().unwrap_or_else(|e| {
+            println!("Translation memory not available, translating without it: {e}");
+            None
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    /// ISO code of the target language
+    lang: String,
+    /// Whether the translation is simplified for learners
+    graded: bool,
+    source: String,
+    trans: Vec<String>,
+}
+
+/// View of the translation memory for a language and a source file
+pub struct Memory {
+    db: sled::Db,
+    lang: String,
+    /// Language, level and options affecting the translation
+    key: String,
+    graded: bool,
+    /// Hash of the neighbours of each source cue, if they must match too
+    context: Option<Vec<u64>>,
+    hits: AtomicUsize,
+}
+
+impl Memory {
+    pub fn new(
+        db: &sled::Db,
+        lang: &Lang,
+        key: &str,
+        graded: bool,
+        source: &[Cue],
+        with_context: bool,
+    ) -> Self {
+        let context = with_context.then(|| {
+            (0..source.len())
+                .map(|i| {
+                    let prev = i.checked_sub(1).map(|p| normalize(&source[p].text));
+                    let next = source.get(i + 1).map(|n| normalize(&n.text));
+                    let hash = fnv1a(prev.unwrap_or_default().as_bytes(), FNV_OFFSET);
+                    let hash = fnv1a(b"\n", hash);
+                    fnv1a(next.unwrap_or_default().as_bytes(), hash)
+                })
+                .collect()
+        });
+        Self {
+            db: db.clone(),
+            lang: lang.iso_code().to_string(),
+            key: key.to_string(),
+            graded,
+            context,
+            hits: AtomicUsize::new(0),
+        }
+    }
+    fn db_key(&self, idx: usize, cue: &Cue) -> String {
+        let ctx = match &self.context {
+            Some(hashes) => format!("{:016x}", hashes[idx]),
+            None => String::new(),
+        };
+        format!("{}\u{0}{}\u{0}{}", self.key, ctx, normalize(&cue.text))
+    }
+    /// Stored translation of the source cue at `idx`, if any
+    pub fn get(&self, idx: usize, cue: &Cue) -> Option<Vec<String>> {
+        let value = self.db.get(self.db_key(idx, cue)).ok()??;
+        let entry: Entry = serde_json::from_slice(&value).ok()?;
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        Some(entry.trans)
+    }
+    pub fn insert(&self, idx: usize, cue: &Cue, trans: &[String]) -> Result<()> {
+        let entry = Entry {
+            lang: self.lang.clone(),
+            graded: self.graded,
+            source: normalize(&cue.text),
+            trans: trans.to_vec(),
+        };
+        self.db
+            .insert(self.db_key(idx, cue), serde_json::to_vec(&entry)?)?;
+        Ok(())
+    }
+    /// Number of cues found in the memory
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+}
+
+/// Lines joined and with collapsed whitespace, as matched in the memory
+fn normalize(lines: &[String]) -> String {
+    let words: Vec<&str> = lines.iter().flat_map(|l| l.split_whitespace()).collect();
+    words.join(" ")
+}
+
+/// Export the translations (but not the graded subtitles) as TMX,
+/// returning the number of translation units
+pub fn export_tmx(db: &sled::Db, fname: &Path, source_lang: &str) -> Result<usize> {
+    let mut units = BTreeSet::new();
+    for item in db.iter() {
+        let (_, value) = item?;
+        let Ok(entry) = serde_json::from_slice::<Entry>(&value) else {
+            continue;
+        };
+        if !entry.graded {
+            units.insert((entry.source, entry.lang, entry.trans.join(" ")));
+        }
+    }
+    let mut out =
+        String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<tmx version=\"1.4\">\n");
+    out.push_str(&format!(
+        "  <header creationtool=\"translate-subs\" creationtoolversion=\"{}\" \
+         segtype=\"sentence\" o-tmf=\"sled\" adminlang=\"en\" srclang=\"{}\" \
+         datatype=\"plaintext\"/>\n  <body>\n",
+        env!("CARGO_PKG_VERSION"),
+        escape(source_lang)
+    ));
+    for (source, lang, trans) in &units {
+        out.push_str("    <tu>\n");
+        out.push_str(&format!(
+            "      <tuv xml:lang=\"{}\"><seg>{}</seg></tuv>\n",
+            escape(source_lang),
+            escape(source)
+        ));
+        out.push_str(&format!(
+            "      <tuv xml:lang=\"{}\"><seg>{}</seg></tuv>\n",
+            escape(lang),
+            escape(trans)
+        ));
+        out.push_str("    </tu>\n");
+    }
+    out.push_str("  </body>\n</tmx>\n");
+    fs::write(fname, out)?;
+    Ok(units.len())
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,720 @@
+/**************************************************************************
+  Copyright 2024 Francesco Versaci (https://github.com/fversaci/)
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+**************************************************************************/
+
+use crate::context::Context;
+use crate::frames::assemble_blocks;
+use crate::glossary::Glossary;
+use crate::progress::Progress;
+use crate::report::Marks;
+use crate::sdh::{self, Sdh};
+use crate::subs::Cue;
+use crate::tags::Masks;
+use crate::tm::Memory;
+use anyhow::{anyhow, Result};
+use async_openai::types::{
+    ChatCompletionRequestSystemMessageArgs, ChatCompletionRequestUserMessageArgs,
+    CreateChatCompletionRequestArgs, CreateMessageRequestArgs, CreateRunRequest,
+    CreateRunRequestArgs, MessageRole, ResponseFormat, ResponseFormatJsonSchema,
+};
+use async_openai::{config::OpenAIConfig, Client};
+use cesco_gpt::talks::get_response;
+use cesco_gpt::talks::lang_practice::{Lang, LangLevel};
+use cesco_gpt::talks::Talk::TranslateSubs;
+use rand::rngs::ThreadRng;
+use rand::Rng;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tokio::sync::{mpsc, Mutex, OwnedSemaphorePermit, Semaphore};
+use tokio::task::JoinSet;
+
+struct RandLabel {
+    rng: ThreadRng,
+}
+
+impl RandLabel {
+    fn new() -> Self {
+        let rng = rand::thread_rng();
+        RandLabel { rng }
+    }
+    fn get_label(&mut self) -> String {
+        let charset: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+
+        let random_string: String = (0..5)
+            .map(|_| {
+                let idx = self.rng.gen_range(0..charset.len());
+                charset[idx] as char
+            })
+            .collect();
+
+        random_string
+    }
+}
+
+/// Settings and state shared by all the translators
+pub struct Shared {
+    pub lang: Lang,
+    /// CEFR level of the graded subtitles, if simplifying them for learners
+    pub level: Option<LangLevel>,
+    /// Handling of the annotations for the deaf and hard of hearing
+    pub sdh: Sdh,
+    /// Model for structured outputs via Chat Completions, instead of the assistant
+    pub chat_model: Option<String>,
+    pub glossary: Glossary,
+    pub context: Context,
+    pub progress: Progress,
+    pub marks: Marks,
+    /// Translation memory, to reuse the lines already translated
+    pub memory: Option<Memory>,
+    /// Global limit on the concurrent queries, shared by all the languages
+    pub limit: Arc<Semaphore>,
+}
+
+enum Backend {
+    /// Assistant thread, replying with free-form text
+    Assistant {
+        thread_id: String,
+        run_request: Box<CreateRunRequest>,
+    },
+    /// Chat Completions, with the reply constrained by a JSON schema
+    Chat { model: String },
+}
+
+pub struct Translator {
+    client: Client<OpenAIConfig>,
+    backend: Backend,
+    shared: Arc<Shared>,
+}
+
+impl Translator {
+    pub async fn new(client: Client<OpenAIConfig>, shared: Arc<Shared>) -> Result<Self> {
+        if let Some(model) = &shared.chat_model {
+            let backend = Backend::Chat {
+                model: model.clone(),
+            };
+            return Ok(Self {
+                client,
+                backend,
+                shared,
+            });
+        }
+        let talk = TranslateSubs {
+            lang: shared.lang.clone(),
+        };
+        let ts = talk.get_conv(&client).await?;
+        let thread = ts.thread;
+        let asst = ts.asst;
+        let run_request = CreateRunRequestArgs::default()
+            .assistant_id(&asst.id)
+            .parallel_tool_calls(false)
+            .build()?;
+        let backend = Backend::Assistant {
+            thread_id: thread.id,
+            run_request: Box::new(run_request),
+        };
+
+        Ok(Self {
+            client,
+            backend,
+            shared,
+        })
+    }
+    async fn translate_str(&self, msg: &str, labels: &[String]) -> Result<String> {
+        match &self.backend {
+            Backend::Assistant {
+                thread_id,
+                run_request,
+            } => {
+                let message = CreateMessageRequestArgs::default()
+                    .role(MessageRole::User)
+                    .content(msg)
+                    .build()?;
+                let _message_obj = self
+                    .client
+                    .threads()
+                    .messages(thread_id)
+                    .create(message)
+                    .await?;
+                let run = self
+                    .client
+                    .threads()
+                    .runs(thread_id)
+                    .create(*run_request.clone())
+                    .await?;
+                get_response(&self.client, &run.id, thread_id).await
+            }
+            Backend::Chat { model } => {
+                let system = match &self.shared.level {
+                    None => format!(
+                        "You are a professional subtitle translator. You will always translate \
+                         the subtitles into {} language, keeping the same JSON keys.",
+                        self.shared.lang
+                    ),
+                    Some(level) => format!(
+                        "You are a language teacher, writing graded subtitles for {level} level \
+                         learners of {} language, keeping the same JSON keys.",
+                        self.shared.lang
+                    ),
+                };
+                let json_schema = ResponseFormatJsonSchema {
+                    description: Some("Translated subtitles, by label".to_string()),
+                    name: "subtitles".to_string(),
+                    schema: Some(labels_schema(labels)),
+                    strict: Some(true),
+                };
+                let request = CreateChatCompletionRequestArgs::default()
+                    .model(model)
+                    .messages([
+                        ChatCompletionRequestSystemMessageArgs::default()
+                            .content(system)
+                            .build()?
+                            .into(),
+                        ChatCompletionRequestUserMessageArgs::default()
+                            .content(msg)
+                            .build()?
+                            .into(),
+                    ])
+                    .response_format(ResponseFormat::JsonSchema { json_schema })
+                    .build()?;
+                let response = self.client.chat().create(request).await?;
+                let message = response
+                    .choices
+                    .into_iter()
+                    .next()
+                    .ok_or(anyhow!("Empty reply."))?
+                    .message;
+                if let Some(refusal) = message.refusal {
+                    return Err(anyhow!("Translation refused: {refusal}"));
+                }
+                message.content.ok_or(anyhow!("Empty reply."))
+            }
+        }
+    }
+    /// Translate the chunk, reusing the cues found in the translation
+    /// memory and sending all the missing ones in a single request, with
+    /// the reused ones between them as context
+    pub async fn translate_job(&mut self, start: usize, chunk: &[Cue]) -> Result<Vec<Cue>> {
+        let shared = self.shared.clone();
+        let Some(memory) = &shared.memory else {
+            let idxs: Vec<usize> = (start..start + chunk.len()).collect();
+            return self.translate_chunk(&idxs, chunk).await;
+        };
+        let mut out: Vec<Option<Cue>> = chunk
+            .iter()
+            .enumerate()
+            .map(|(i, cue)| {
+                let text = memory.get(start + i, cue)?;
+                Some(Cue {
+                    text,
+                    ..cue.clone()
+                })
+            })
+            .collect();
+        for (i, cue) in out.iter().enumerate() {
+            if let Some(cue) = cue {
+                shared.context.accept(start + i, std::slice::from_ref(cue));
+            }
+        }
+        let missing: Vec<usize> = (0..chunk.len()).filter(|&i| out[i].is_none()).collect();
+        if !missing.is_empty() {
+            let idxs: Vec<usize> = missing.iter().map(|i| start + i).collect();
+            let cues: Vec<Cue> = missing.iter().map(|&i| chunk[i].clone()).collect();
+            let trans = self.translate_chunk(&idxs, &cues).await?;
+            for (i, cue) in missing.into_iter().zip(trans) {
+                out[i] = Some(cue);
+            }
+        }
+        Ok(out.into_iter().flatten().collect())
+    }
+    /// Translate the chunk, whose cues have the indices `idxs` among all the
+    /// source cues (they are consecutive, unless some were found in the memory)
+    pub async fn translate_chunk(&mut self, idxs: &[usize], chunk: &[Cue]) -> Result<Vec<Cue>> {
+        // try and translate it
+        let rand = RandLabel::new();
+        let (masks, text) = Masks::new(chunk, self.shared.sdh == Sdh::Keep);
+        let (in_labs, json_str) = chunk_to_json(rand, idxs, chunk, text, &masks, &self.shared)?;
+        let trans_json_str = self.translate_str(&json_str, &in_labs).await?;
+        let ret = json_to_chunk(&trans_json_str, in_labs, chunk, idxs);
+        if let Ok((mut trans, spread)) = ret {
+            for &idx in &spread {
+                self.shared.marks.spread(idxs[idx]);
+            }
+            let repaired = masks.restore(chunk, &mut trans);
+            for &idx in &repaired {
+                self.shared.marks.tags(idxs[idx]);
+            }
+            // only the cues translated on their own, and without issues, are memorized
+            if let Some(memory) = &self.shared.memory {
+                for (idx, (cue, t)) in chunk.iter().zip(&trans).enumerate() {
+                    if spread.contains(&idx) || repaired.contains(&idx) {
+                        continue;
+                    }
+                    if let Err(e) = memory.insert(idxs[idx], cue, &t.text) {
+                        self.shared.progress.log(format!("Translation memory: {e}"));
+                    }
+                }
+            }
+            for (&idx, cue) in idxs.iter().zip(&trans) {
+                self.shared.context.accept(idx, std::slice::from_ref(cue));
+            }
+            return Ok(trans);
+        }
+        // Something went wrong, print error and replace with a new translator
+        let progress = &self.shared.progress;
+        progress.retry();
+        progress.log(format!("Error detected: {}", ret.err().unwrap()));
+        let new_trans = Translator::new(self.client.clone(), self.shared.clone()).await?;
+        *self = new_trans;
+        let progress = &self.shared.progress;
+        // Couldn't translate even a single block, give up and use the original text
+        if chunk.len() == 1 {
+            let seq = chunk.first().unwrap().sequence;
+            progress.log(format!("Copying verbatim block {}", seq));
+            self.shared.marks.verbatim(idxs[0]);
+            return Ok(chunk.to_vec());
+        }
+        // More lines, try divide et impera
+        progress.log(format!(
+            "Is chunk size too big? Dividing chunk {}-{}",
+            chunk.first().unwrap().sequence,
+            chunk.last().unwrap().sequence
+        ));
+        let mid = chunk.len() / 2;
+        let (chunk_up, chunk_down) = chunk.split_at(mid);
+        let (idxs_up, idxs_down) = idxs.split_at(mid);
+        let trans_up = Box::pin(self.translate_chunk(idxs_up, chunk_up)).await?;
+        let trans_down = Box::pin(self.translate_chunk(idxs_down, chunk_down)).await?;
+        let merged = trans_up.iter().chain(trans_down.iter()).cloned().collect();
+        Ok(merged)
+    }
+}
+
+/// A chunk to be translated, covering the cues `start..start+chunk.len()`
+pub struct Job {
+    pub start: usize,
+    pub hash: String,
+    pub chunk: Vec<Cue>,
+}
+
+/// Called by the translators on each completed job (e.g., to save a checkpoint)
+pub type OnDone = Arc<dyn Fn(&Job, &[Cue]) -> Result<()> + Send + Sync>;
+
+/// Position of the job in the queue, its translation and its window slot
+type JobResult = (usize, Result<Vec<Cue>>, OwnedSemaphorePermit);
+
+pub struct TranslatorPool {
+    translators: Vec<Translator>,
+    shared: Arc<Shared>,
+}
+
+impl TranslatorPool {
+    pub async fn new(
+        num: usize,
+        client: Client<OpenAIConfig>,
+        shared: Arc<Shared>,
+    ) -> Result<Self> {
+        if num == 0 {
+            return Err(anyhow!("Error: pool must have at least 1 translator."));
+        }
+        let mut translators = Vec::new();
+        for _ in 0..num {
+            let translator = Translator::new(client.clone(), shared.clone()).await?;
+            translators.push(translator);
+        }
+        Ok(Self {
+            translators,
+            shared,
+        })
+    }
+    /// Translate the jobs, returning the translations in the original order.
+    ///
+    /// Each translator takes the next job from a shared queue as soon as
+    /// it is free, so that a slow chunk does not stall the others. At most
+    /// `window` jobs are queued or running past the oldest unfinished one.
+    pub async fn translate(
+        self,
+        jobs: Vec<Job>,
+        window: usize,
+        on_done: OnDone,
+    ) -> Result<Vec<Vec<Cue>>> {
+        let num_jobs = jobs.len();
+        let window = Arc::new(Semaphore::new(window.max(1)));
+        let (job_tx, job_rx) = mpsc::unbounded_channel::<(usize, Job, OwnedSemaphorePermit)>();
+        let job_rx = Arc::new(Mutex::new(job_rx));
+        let (res_tx, mut res_rx) = mpsc::unbounded_channel::<JobResult>();
+        // feed the queue, waiting for the window to move forward
+        tokio::spawn(async move {
+            for (pos, job) in jobs.into_iter().enumerate() {
+                let Ok(permit) = window.clone().acquire_owned().await else {
+                    break;
+                };
+                if job_tx.send((pos, job, permit)).is_err() {
+                    break;
+                }
+            }
+        });
+        // the workers are awaited before returning, so that they release `on_done`
+        let mut workers = JoinSet::new();
+        for mut t in self.translators {
+            let job_rx = job_rx.clone();
+            let res_tx = res_tx.clone();
+            let on_done = on_done.clone();
+            let limit = self.shared.limit.clone();
+            workers.spawn(async move {
+                loop {
+                    let next = job_rx.lock().await.recv().await;
+                    let Some((pos, job, permit)) = next else {
+                        break;
+                    };
+                    let Ok(_slot) = limit.acquire().await else {
+                        break;
+                    };
+                    let res = t.translate_job(job.start, &job.chunk).await;
+                    let res = res.and_then(|trans| {
+                        on_done(&job, &trans)?;
+                        Ok(trans)
+                    });
+                    if res_tx.send((pos, res, permit)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(res_tx);
+        // reorder the results, releasing the window slots of the completed prefix
+        let mut pending = BTreeMap::new();
+        let mut out = Vec::with_capacity(num_jobs);
+        while out.len() < num_jobs {
+            let (pos, res, permit) = res_rx
+                .recv()
+                .await
+                .ok_or(anyhow!("Translators stopped unexpectedly."))?;
+            self.shared.progress.inc();
+            pending.insert(pos, (res?, permit));
+            while let Some((trans, _permit)) = pending.remove(&out.len()) {
+                out.push(trans);
+            }
+        }
+        while let Some(res) = workers.join_next().await {
+            res?;
+        }
+        Ok(out)
+    }
+}
+
+/// Main instruction of the query, to translate or to simplify the subtitles
+pub fn command(lang: &Lang, level: Option<&LangLevel>) -> String {
+    match level {
+        None => format!(
+            "Translate these JSON subtitles into {} language. Your output must also be in JSON format.\n",
+            lang
+        ),
+        // graded subtitles, also when the source is already in the target language
+        Some(level) => format!(
+            "Rewrite these JSON subtitles in simple {lang} language, translating them if needed, \
+             so that they can be understood by a {level} level learner: use common words and \
+             simple grammar, but keep the meaning and the tone. Your output must also be in JSON format.\n"
+        ),
+    }
+}
+
+fn chunk_to_json(
+    mut rand: RandLabel,
+    idxs: &[usize],
+    chunk: &[Cue],
+    chunk_text: Vec<Vec<String>>,
+    masks: &Masks,
+    shared: &Shared,
+) -> Result<(Vec<String>, String)> {
+    let chunk_dict: BTreeMap<String, Vec<String>> = chunk_text
+        .into_iter()
+        .enumerate()
+        .map(|(a, b)| (format!("{:03}{}", a, rand.get_label()), b))
+        .collect();
+    let chunk_labs: Vec<String> = chunk_dict.keys().cloned().collect();
+    let json_str = serde_json::to_string_pretty(&chunk_dict)?;
+    let cmd = command(&shared.lang, shared.level.as_ref());
+    let glossary = shared.glossary.instructions(chunk).unwrap_or_default();
+    let tags = masks.instructions().unwrap_or_default();
+    let sdh = match shared.sdh {
+        Sdh::Translate => sdh::instructions(chunk).unwrap_or_default(),
+        _ => String::new(),
+    };
+    let context = shared.context.instructions(idxs);
+    let cmd_json_str = format!(
+        "{}{}{}{}{}{}",
+        cmd,
+        glossary,
+        tags,
+        sdh,
+        context.unwrap_or_default(),
+        json_str
+    );
+    Ok((chunk_labs, cmd_json_str))
+}
+
+/// JSON schema of the translation, with exactly the given labels as keys
+fn labels_schema(labels: &[String]) -> Value {
+    let props: serde_json::Map<String, Value> = labels
+        .iter()
+        .map(|lab| {
+            (
+                lab.clone(),
+                json!({"type": "array", "items": {"type": "string"}}),
+            )
+        })
+        .collect();
+    json!({
+        "type": "object",
+        "properties": props,
+        "required": labels,
+        "additionalProperties": false,
+    })
+}
+
+/// Index of the cue encoded in the numeric prefix of the label
+fn label_index(label: &str) -> Option<usize> {
+    let prefix: String = label.chars().take_while(|c| c.is_ascii_digit()).collect();
+    prefix.parse().ok()
+}
+
+/// Parse the translated JSON, tolerating the most common deviations:
+/// surrounding prose or code fences, strings instead of arrays of lines,
+/// and labels with a mangled random suffix, which are matched by their
+/// numeric index. Unknown labels are dropped, but at least one must be found.
+fn parse_trans(json_str: &str, in_labs: &[String]) -> Result<BTreeMap<String, Vec<String>>> {
+    let beg = json_str
+        .find('{')
+        .ok_or(anyhow!("No JSON object in reply."))?;
+    let end = json_str
+        .rfind('}')
+        .ok_or(anyhow!("No JSON object in reply."))?;
+    let obj: serde_json::Map<String, Value> = serde_json::from_str(&json_str[beg..=end])?;
+    let mut trans_dict = BTreeMap::new();
+    for (label, value) in obj {
+        let label = if in_labs.contains(&label) {
+            label
+        } else {
+            let idx = label_index(&label);
+            match in_labs
+                .iter()
+                .find(|lab| idx.is_some() && label_index(lab) == idx)
+            {
+                Some(lab) => lab.clone(),
+                None => continue,
+            }
+        };
+        if trans_dict.contains_key(&label) {
+            return Err(anyhow!("Duplicate label in translated chunk."));
+        }
+        let lines = match value {
+            Value::String(line) => vec![line],
+            Value::Array(lines) => lines
+                .into_iter()
+                .map(|l| match l {
+                    Value::String(l) => Ok(l),
+                    _ => Err(anyhow!("Unexpected value in translated chunk.")),
+                })
+                .collect::<Result<_>>()?,
+            _ => return Err(anyhow!("Unexpected value in translated chunk.")),
+        };
+        trans_dict.insert(label, lines);
+    }
+    if trans_dict.is_empty() {
+        return Err(anyhow!("No labels in translated chunk."));
+    }
+    Ok(trans_dict)
+}
+
+/// Max share of labels missing from a reply, beyond which it is rejected
+const MAX_MISSING: f64 = 0.5;
+
+/// Translated cues, together with the indices of those sharing a block
+/// spread across frames. Replies missing too many cues, or merging cues
+/// which are not consecutive in `idxs`, are rejected, so that the chunk
+/// gets divided instead.
+fn json_to_chunk(
+    json_str: &str,
+    in_labs: Vec<String>,
+    in_chunk: &[Cue],
+    idxs: &[usize],
+) -> Result<(Vec<Cue>, Vec<usize>)> {
+    let trans_dict = parse_trans(json_str, &in_labs)?;
+    let missing = in_labs.len() - trans_dict.len();
+    if missing as f64 > MAX_MISSING * in_labs.len() as f64 {
+        return Err(anyhow!("Too many missing labels in translated chunk."));
+    }
+    let mut in_curr = 0;
+    let mut trans_iter = trans_dict.into_iter().peekable();
+    let mut out_chunk = Vec::new();
+    let mut spread = Vec::new();
+    while let Some((trans_label, trans_data)) = trans_iter.next() {
+        let in_label = in_labs.get(in_curr);
+        if in_label.is_none() {
+            return Err(anyhow!("Exhausted input."));
+        }
+        let in_label = in_label.unwrap();
+        if *in_label != trans_label {
+            return Err(anyhow!("Missing label in translated chunk."));
+        }
+        let trans_blocks;
+        if trans_iter.peek().is_none() {
+            trans_blocks = assemble_blocks(&in_chunk[in_curr..], &trans_data);
+        } else {
+            let next_label = &trans_iter.peek().unwrap().0;
+            let mut num_blocks = 0;
+            let mut found = false;
+            for in_lab in &in_labs[in_curr..] {
+                if in_lab == next_label {
+                    found = true;
+                    break;
+                }
+                num_blocks += 1;
+            }
+            if found {
+                trans_blocks =
+                    assemble_blocks(&in_chunk[in_curr..in_curr + num_blocks], &trans_data);
+                in_curr += num_blocks;
+            } else {
+                return Err(anyhow!("Label not found."));
+            }
+        }
+        if trans_blocks.len() > 1 {
+            let merged = out_chunk.len()..out_chunk.len() + trans_blocks.len();
+            // e.g., a cue found in the translation memory lies between them
+            if idxs[merged.clone()].windows(2).any(|w| w[1] != w[0] + 1) {
+                return Err(anyhow!("Merged cues which are not consecutive."));
+            }
+            spread.extend(merged);
+        }
+        out_chunk.extend(trans_blocks);
+    }
+    if out_chunk.len() != in_chunk.len() {
+        return Err(anyhow!("Wrong number of cues in translated chunk."));
+    }
+    Ok((out_chunk, spread))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn cue(seq: u32, text: &str) -> Cue {
+        Cue {
+            sequence: seq,
+            start: Duration::from_secs(seq as u64),
+            end: Duration::from_secs(seq as u64 + 1),
+            text: vec![text.to_string()],
+            meta: Default::default(),
+        }
+    }
+
+    /// Translation of a chunk sent with all its cues
+    fn to_chunk(reply: &str, chunk: &[Cue]) -> Result<(Vec<Cue>, Vec<usize>)> {
+        let idxs: Vec<usize> = (0..chunk.len()).collect();
+        json_to_chunk(reply, labels(chunk.len()), chunk, &idxs)
+    }
+
+    fn labels(num: usize) -> Vec<String> {
+        (0..num).map(|i| format!("{i:03}abcde")).collect()
+    }
+
+    #[test]
+    fn parse_trans_maps_mangled_labels_by_index() {
+        let labs = labels(12);
+        let reply = r#"Here it is: {"001xyz": "uno", "010abcde": ["dieci"], "011": ["undici"]}"#;
+        let dict = parse_trans(reply, &labs).unwrap();
+        let keys: Vec<_> = dict.keys().cloned().collect();
+        assert_eq!(
+            keys,
+            vec![labs[1].clone(), labs[10].clone(), labs[11].clone()]
+        );
+        assert_eq!(dict[&labs[1]], vec!["uno"]);
+    }
+
+    #[test]
+    fn parse_trans_rejects_unknown_and_duplicate_labels() {
+        let labs = labels(2);
+        assert!(parse_trans(r#"{"foo": ["a"], "099abcde": ["b"]}"#, &labs).is_err());
+        assert!(parse_trans(r#"{"000abcde": ["a"], "000zzz": ["b"]}"#, &labs).is_err());
+        assert!(parse_trans("no json", &labs).is_err());
+    }
+
+    #[test]
+    fn json_to_chunk_keeps_one_cue_per_label() {
+        let chunk = vec![cue(1, "one"), cue(2, "two")];
+        let reply = r#"{"000abcde": ["uno"], "001abcde": ["due"]}"#;
+        let (trans, spread) = to_chunk(reply, &chunk).unwrap();
+        assert_eq!(trans.len(), 2);
+        assert_eq!(trans[1].text, vec!["due"]);
+        assert_eq!(trans[1].sequence, 2);
+        assert!(spread.is_empty());
+    }
+
+    #[test]
+    fn json_to_chunk_spreads_merged_cues() {
+        let chunk = vec![cue(1, "one"), cue(2, "two"), cue(3, "three")];
+        let reply = r#"{"000abcde": ["uno due"], "002abcde": ["tre"]}"#;
+        let (trans, spread) = to_chunk(reply, &chunk).unwrap();
+        assert_eq!(trans.len(), 3);
+        assert_eq!(spread, vec![0, 1]);
+    }
+
+    #[test]
+    fn json_to_chunk_spreads_long_merges() {
+        let chunk: Vec<_> = (1..=6).map(|i| cue(i, "text")).collect();
+        let reply =
+            r#"{"000abcde": ["a"], "001abcde": ["uno due tre quattro"], "005abcde": ["f"]}"#;
+        let (trans, spread) = to_chunk(reply, &chunk).unwrap();
+        assert_eq!(trans.len(), 6);
+        assert_eq!(spread, vec![1, 2, 3, 4]);
+        assert!(trans.iter().all(|c| !c.text.is_empty()));
+    }
+
+    #[test]
+    fn json_to_chunk_does_not_repeat_short_merges() {
+        let chunk: Vec<_> = (1..=5).map(|i| cue(i, "text")).collect();
+        let reply = r#"{"000abcde": ["Ciao"], "003abcde": ["d"], "004abcde": ["e"]}"#;
+        let (trans, _) = to_chunk(reply, &chunk).unwrap();
+        let full: Vec<_> = trans[..3].iter().filter(|c| !c.text.is_empty()).collect();
+        assert_eq!(full.len(), 1);
+        assert_eq!(full[0].text, vec!["Ciao"]);
+    }
+
+    #[test]
+    fn json_to_chunk_rejects_too_many_missing_labels() {
+        let chunk: Vec<_> = (1..=6).map(|i| cue(i, "text")).collect();
+        let reply = r#"{"000abcde": ["a"], "005abcde": ["f"]}"#;
+        assert!(to_chunk(reply, &chunk).is_err());
+    }
+
+    #[test]
+    fn json_to_chunk_rejects_merges_across_gaps() {
+        let chunk = vec![cue(1, "one"), cue(3, "three"), cue(4, "four")];
+        let reply = r#"{"000abcde": ["uno e tre"], "002abcde": ["quattro"]}"#;
+        assert!(json_to_chunk(reply, labels(3), &chunk, &[0, 2, 3]).is_err());
+        let reply = r#"{"000abcde": ["uno"], "001abcde": ["tre e quattro"]}"#;
+        assert!(json_to_chunk(reply, labels(3), &chunk, &[0, 2, 3]).is_ok());
+    }
+
+    #[test]
+    fn json_to_chunk_rejects_missing_first_label() {
+        let chunk = vec![cue(1, "one"), cue(2, "two")];
+        assert!(to_chunk(r#"{"001abcde": ["due"]}"#, &chunk).is_err());
+        assert!(to_chunk(r#"{"bad": ["due"]}"#, &chunk).is_err());
+    }
+}
@@ -0,0 +1,168 @@
+/**************************************************************************
+  Copyright 2024 Francesco Versaci (https://github.com/fversaci/)
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+**************************************************************************/
+
+//! Vocabulary sheet of the difficult words and idioms, to study before watching
+
+use crate::chunker::Chunker;
+use crate::helper::{find_json, Helper};
+use crate::progress::Progress;
+use crate::report::timestamp;
+use crate::subs::{plain_text, Cue};
+use anyhow::{anyhow, Result};
+use async_openai::{config::OpenAIConfig, Client};
+use cesco_gpt::talks::lang_practice::{Lang, LangLevel};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+enum VocabFormat {
+    Csv,
+    Markdown,
+    /// Tab-separated front and back of Anki notes
+    Anki,
+}
+
+impl VocabFormat {
+    fn from_path(fname: &Path) -> Result<Self> {
+        let ext = fname.extension().unwrap_or_default().to_string_lossy();
+        match ext.to_lowercase().as_str() {
+            "csv" => Ok(Self::Csv),
+            "md" => Ok(Self::Markdown),
+            "tsv" | "txt" => Ok(Self::Anki),
+            _ => Err(anyhow!(
+                "Unknown vocabulary format: {} (use csv, md or tsv)",
+                fname.display()
+            )),
+        }
+    }
+}
+
+/// A difficult word or idiom, as found by the model
+#[derive(Deserialize)]
+struct Found {
+    term: String,
+    translation: String,
+    /// Label of the cue where the term occurs
+    label: String,
+}
+
+struct Entry {
+    term: String,
+    translation: String,
+    example: String,
+    time: String,
+}
+
+fn csv_field(txt: &str) -> String {
+    format!("\"{}\"", txt.replace('"', "\"\""))
+}
+
+fn render(entries: &[Entry], format: &VocabFormat) -> String {
+    let mut out = String::new();
+    match format {
+        VocabFormat::Csv => {
+            out.push_str("term,translation,example,time\n");
+            for e in entries {
+                let fields = [&e.term, &e.translation, &e.example, &e.time];
+                let fields: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+                out.push_str(&fields.join(","));
+                out.push('\n');
+            }
+        }
+        VocabFormat::Markdown => {
+            out.push_str("| Term | Translation | Example | Time |\n");
+            out.push_str("|---|---|---|---|\n");
+            for e in entries {
+                let fields = [&e.term, &e.translation, &e.example, &e.time];
+                let fields: Vec<String> = fields.iter().map(|f| f.replace('|', "\\|")).collect();
+                out.push_str(&format!("| {} |\n", fields.join(" | ")));
+            }
+        }
+        VocabFormat::Anki => {
+            for e in entries {
+                let clean = |txt: &str| txt.replace(['\t', '\n'], " ");
+                out.push_str(&format!(
+                    "{}\t{}<br><i>{}</i> ({})\n",
+                    clean(&e.term),
+                    clean(&e.translation),
+                    clean(&e.example),
+                    e.time
+                ));
+            }
+        }
+    }
+    out
+}
+
+/// Write the words and idioms of the subtitles likely above the given
+/// level, with their translation into `lang`, an example and its time
+pub async fn run(
+    client: &Client<OpenAIConfig>,
+    cues: &[Cue],
+    chunker: &Chunker,
+    lang: &Lang,
+    level: &LangLevel,
+    out_fname: &Path,
+) -> Result<()> {
+    let format = VocabFormat::from_path(out_fname)?;
+    let chunks = chunker.split(cues);
+    let progress = Progress::new(chunks.len(), 0);
+    let helper = Helper::new(client).await?;
+    let mut seen = HashSet::new();
+    let mut entries = Vec::new();
+    for chunk in chunks {
+        let dict: BTreeMap<String, String> = chunk
+            .iter()
+            .enumerate()
+            .map(|(i, cue)| (format!("{i:03}"), plain_text(&cue.text.join(" "))))
+            .collect();
+        let query = format!(
+            "Find the words and idioms in these JSON subtitles which are likely unknown to \
+             a {level} level learner of their language. Reply only with a JSON array of \
+             objects with the fields \"term\" (in dictionary form), \"translation\" (into \
+             {lang} language) and \"label\" (the key of the subtitle where it occurs).\n\n{}",
+            serde_json::to_string_pretty(&dict)?
+        );
+        let resp = helper.ask(&query).await?;
+        match serde_json::from_str::<Vec<Found>>(find_json(&resp, '[', ']')) {
+            Ok(found) => {
+                for f in found {
+                    let Some(cue) = f.label.parse::<usize>().ok().and_then(|i| chunk.get(i)) else {
+                        continue;
+                    };
+                    // keep the first occurrence of each term
+                    if !seen.insert(f.term.to_lowercase()) {
+                        continue;
+                    }
+                    entries.push(Entry {
+                        term: f.term,
+                        translation: f.translation,
+                        example: plain_text(&cue.text.join(" ")),
+                        time: timestamp(cue.start),
+                    });
+                }
+            }
+            Err(e) => progress.log(format!("Skipping invalid vocabulary reply: {e}")),
+        }
+        progress.inc();
+    }
+    progress.finish();
+    helper.close().await?;
+    fs::write(out_fname, render(&entries, &format))?;
+    println!("{} terms saved in {}", entries.len(), out_fname.display());
+    Ok(())
+}
@@ -17,8 +17,8 @@
 use anyhow::{anyhow, Error, Result};
 use async_openai::types::{
     AssistantEventStream, AssistantObject, AssistantStreamEvent, CreateMessageRequestArgs,
-    CreateThreadRequestArgs, MessageContent, MessageDeltaContent, MessageRole, RunStatus,
-    ThreadObject,
+    CreateThreadRequestArgs, MessageContent, MessageDeltaContent, MessageRole, RunCompletionUsage,
+    RunStatus, ThreadObject,
 };
 use async_openai::{config::OpenAIConfig, Client};
 use strum_macros::{Display, EnumIter, EnumString};
@@ -29,6 +29,7 @@ mod summarize;
 mod translate_subs;
 use clap::Subcommand;
 use lang_practice::{Lang, LangLevel};
+use serde::{Deserialize, Serialize};
 use tokio_stream::{Stream, StreamExt};
 
 pub struct TalkStart {
@@ -38,7 +39,9 @@ pub struct TalkStart {
     pub presuff: (String, String),
 }
 
-#[derive(Default, Display, Debug, Clone, EnumIter, EnumString, Subcommand)]
+#[derive(
+    Default, Display, Debug, Clone, EnumIter, EnumString, Subcommand, Serialize, Deserialize,
+)]
 pub enum Talk {
     /// Generic Chat-GPT prompt
     #[default]
@@ -111,7 +114,22 @@ async fn get_asst_thread(
     }
 }
 
-pub fn stream_messages(mut stream: AssistantEventStream) -> impl Stream<Item = Result<String>> {
+/// Events of a streamed run: text deltas and, at completion, the token usage
+#[derive(Debug, Clone)]
+pub enum StreamEvent {
+    Delta(String),
+    Usage(RunCompletionUsage),
+}
+
+pub fn stream_messages(stream: AssistantEventStream) -> impl Stream<Item = Result<String>> {
+    stream_events(stream).filter_map(|event| match event {
+        Ok(StreamEvent::Delta(text)) => Some(Ok(text)),
+        Ok(StreamEvent::Usage(_)) => None,
+        Err(e) => Some(Err(e)),
+    })
+}
+
+pub fn stream_events(mut stream: AssistantEventStream) -> impl Stream<Item = Result<StreamEvent>> {
     async_stream::stream! {
         while let Some(event) = stream.next().await {
             match event {
@@ -125,17 +143,21 @@ pub fn stream_messages(mut stream: AssistantEventStream) -> impl Stream<Item = R
                                     if let Some(text) = &text_object.text {
                                     // Check if `text.value` has content
                                         if let Some(value) = &text.value {
-                                            yield Ok(value.clone());
+                                            yield Ok(StreamEvent::Delta(value.clone()));
                                         }
                                     }
                                 }
                             }
                         }
                     }
+                    AssistantStreamEvent::ThreadRunCompleted(run) => {
+                        if let Some(usage) = run.usage {
+                            yield Ok(StreamEvent::Usage(usage));
+                        }
+                    }
                     AssistantStreamEvent::ThreadRunCreated(_)
                     | AssistantStreamEvent::ThreadRunQueued(_)
                     | AssistantStreamEvent::ThreadRunInProgress(_)
-                    | AssistantStreamEvent::ThreadRunCompleted(_)
                     | AssistantStreamEvent::ThreadRunStepCreated(_)
                     | AssistantStreamEvent::ThreadRunStepInProgress(_)
                     | AssistantStreamEvent::ThreadRunStepCompleted(_)
@@ -188,7 +210,7 @@ pub async fn get_response(
                 }
             }
             RunStatus::InProgress | RunStatus::Queued => {
-                std::thread::sleep(std::time::Duration::from_millis(250));
+                tokio::time::sleep(std::time::Duration::from_millis(250)).await;
             }
             _ => {
                 return Err(anyhow!("{:?}: {:?}", run.status, run.last_error));
@@ -18,11 +18,14 @@ use anyhow::{Error, Result};
 use async_openai::types::CreateRunRequestArgs;
 use async_openai::{config::OpenAIConfig, Client};
 use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
 use strum_macros::{Display, EnumIter, EnumString};
 
 use super::get_response;
 
-#[derive(Default, Display, Debug, Clone, EnumIter, EnumString, ValueEnum)]
+#[derive(
+    Default, Display, Debug, Clone, EnumIter, EnumString, ValueEnum, Serialize, Deserialize,
+)]
 pub enum Lang {
     #[default]
     English,
@@ -33,9 +36,33 @@ pub enum Lang {
     Latin,
     Italian,
     Interlingua,
+    Chinese,
+    Japanese,
+    Thai,
 }
 
-#[derive(Default, Display, Debug, Clone, EnumIter, EnumString, ValueEnum)]
+impl Lang {
+    /// ISO 639-1 code, e.g., for naming localized files
+    pub fn iso_code(&self) -> &'static str {
+        match self {
+            Lang::English => "en",
+            Lang::German => "de",
+            Lang::French => "fr",
+            Lang::Spanish => "es",
+            Lang::Catalan => "ca",
+            Lang::Latin => "la",
+            Lang::Italian => "it",
+            Lang::Interlingua => "ia",
+            Lang::Chinese => "zh",
+            Lang::Japanese => "ja",
+            Lang::Thai => "th",
+        }
+    }
+}
+
+#[derive(
+    Default, Display, Debug, Clone, EnumIter, EnumString, ValueEnum, Serialize, Deserialize,
+)]
 pub enum LangLevel {
     #[default]
     A1,